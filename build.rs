@@ -0,0 +1,68 @@
+use std::{env, fs, path::Path};
+
+// Always the same size, so that embedding the symbols does not move the code they describe
+const SYMBOL_TABLE_SIZE: usize = 512 * 1024;
+
+// Attached to QEMU next to the boot image, see the bootimage arguments in Cargo.toml
+const DISK_IMAGES: [&str; 3] = ["disk.img", "test-disk.img", "test-virtio-disk.img"];
+const DISK_IMAGE_SIZE: u64 = 64 * 1024 * 1024;
+
+// Turns the output of `nm -n -C` on the previous build into the symbol table used for backtraces.
+// `run_os.sh` builds twice, so the second build has the symbols of the first one
+fn main() {
+    let manifest_dir = env::var("CARGO_MANIFEST_DIR").unwrap();
+    let symbols_path = Path::new(&manifest_dir).join("target").join("kernel.sym");
+    println!("cargo:rerun-if-changed={}", symbols_path.display());
+    println!("cargo:rerun-if-changed=build.rs");
+
+    let mut table = Vec::with_capacity(SYMBOL_TABLE_SIZE);
+
+    let symbols = fs::read_to_string(&symbols_path).unwrap_or_default();
+    for line in symbols.lines() {
+        // Lines look like "0000000000201a30 T core::panicking::panic::h7e2f8a4c1b8d3f21"
+        let mut parts = line.splitn(3, ' ');
+        let (address, name) = match (parts.next(), parts.next(), parts.next()) {
+            (Some(address), Some("t" | "T"), Some(name)) => (address, name),
+            _ => continue,
+        };
+
+        let entry = format!("{} {}\n", address, strip_hash(name));
+        if table.len() + entry.len() > SYMBOL_TABLE_SIZE {
+            println!("cargo:warning=Symbol table is full, some symbols are missing");
+            break;
+        }
+        table.extend_from_slice(entry.as_bytes());
+    }
+
+    table.resize(SYMBOL_TABLE_SIZE, 0);
+
+    let out_dir = env::var("OUT_DIR").unwrap();
+    fs::write(Path::new(&out_dir).join("symbols.bin"), table).unwrap();
+
+    create_disk_images(&Path::new(&manifest_dir).join("target"));
+}
+
+// Empty images the OS formats itself, existing ones are kept with their saved data
+fn create_disk_images(target_dir: &Path) {
+    fs::create_dir_all(target_dir).unwrap();
+
+    for name in DISK_IMAGES {
+        let path = target_dir.join(name);
+        // So a deleted image is created again by the next build
+        println!("cargo:rerun-if-changed={}", path.display());
+        if !path.exists() {
+            let image = fs::File::create(&path).unwrap();
+            image.set_len(DISK_IMAGE_SIZE).unwrap();
+        }
+    }
+}
+
+// Removes the "::h0123456789abcdef" suffix of legacy mangled names, it is only noise in a backtrace
+fn strip_hash(name: &str) -> &str {
+    match name.rsplit_once("::h") {
+        Some((path, hash)) if hash.len() == 16 && hash.chars().all(|c| c.is_ascii_hexdigit()) => {
+            path
+        }
+        _ => name,
+    }
+}
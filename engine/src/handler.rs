@@ -1,10 +1,16 @@
 use crate::SearchResult;
 
+// Number of nodes visited between each call to `SearchHandler::yield_now`
+pub const YIELD_INTERVAL: u64 = 4096;
+
 pub trait SearchHandler {
     fn new_result(&mut self, result: SearchResult);
 
     fn should_stop(&self) -> bool;
 
+    // Called during the search to let the caller do some work without stopping it
+    fn yield_now(&mut self) {}
+
     fn default_handler() -> DefaultHandler {
         DefaultHandler {}
     }
@@ -5,7 +5,7 @@ use cozy_chess::{BitBoard, Board, Move, Piece};
 use crate::{
     engine::MAX_DEPTH,
     evaluation::nnue::NNUE,
-    handler::SearchHandler,
+    handler::{SearchHandler, YIELD_INTERVAL},
     search::{
         position::Position,
         tt::{EntryType, TTEntry},
@@ -187,6 +187,9 @@ impl<'a> Searcher<'a> {
         }
 
         stats.nodes_visited += 1;
+        if stats.nodes_visited % YIELD_INTERVAL == 0 {
+            shared.handler.yield_now();
+        }
 
         if depth == 0 {
             _return!(if quiese {
@@ -1,5 +1,5 @@
 use crate::{
-    handler::SearchHandler,
+    handler::{SearchHandler, YIELD_INTERVAL},
     search::{position::Position, searcher::Searcher, see::static_exchange, SearchSharedState},
     Eval, SearchStats,
 };
@@ -23,6 +23,9 @@ impl<'a> Searcher<'a> {
 
         let current_score = pos.eval(self.nnue);
         stats.nodes_visited += 1;
+        if stats.nodes_visited % YIELD_INTERVAL == 0 {
+            shared.handler.yield_now();
+        }
 
         if itt.len() == 0 {
             return Some(current_score);
@@ -1,4 +1,5 @@
-use alloc::{boxed::Box, vec};
+use alloc::{boxed::Box, collections::TryReserveError, vec, vec::Vec};
+use core::mem::size_of;
 
 
 
@@ -62,6 +63,23 @@ impl TranspositionTable {
         }
     }
 
+    // Same as `new`, but gives back an error instead of aborting when out of memory
+    pub fn try_new(size: TableSize) -> Result<Self, TryReserveError> {
+        let len = size.to_vec_size::<TTEntry>();
+        let mut values = Vec::new();
+        values.try_reserve_exact(len)?;
+        values.resize(len, TTEntry::default());
+        Ok(Self {
+            table: values.into_boxed_slice(),
+            size: len,
+            num_valid_entries: 0,
+        })
+    }
+
+    pub fn table_size(&self) -> TableSize {
+        TableSize::from_bytes(self.size * size_of::<TTEntry>())
+    }
+
     pub fn get(&self, pos: &Position) -> Option<TTEntry> {
         let hash = pos.board().hash();
         let index = self.to_entry_hash(hash);
@@ -146,6 +164,14 @@ fn test_table() {
     assert!(table.get(&pos).is_none(), "Got an invalid result");
 }
 
+#[test]
+fn test_try_new() {
+    let table = TranspositionTable::try_new(TableSize::from_kb(64)).unwrap();
+    assert_eq!(table.size, TableSize::from_kb(64).to_vec_size::<TTEntry>());
+
+    assert!(TranspositionTable::try_new(TableSize::from_bytes(usize::MAX)).is_err());
+}
+
 // #[test]
 // fn test_collisions() {
 //     use crate::utils::positiongen::PositionGenerator;
@@ -8,7 +8,16 @@ killgroup(){
   kill 0
 }
 
-cargo run $1 & # In case of release mode added
+PROFILE=debug
+if [ "$1" = "--release" ]; then
+  PROFILE=release
+fi
+
+# Building twice so the second build embeds the symbols of the first one, for backtraces
+cargo build $1 # In case of release mode added
+nm -n -C target/x86_64/$PROFILE/bmc-os > target/kernel.sym
+
+cargo run $1 &
 sleep 1
 vncviewer :5900 &
 wait
\ No newline at end of file
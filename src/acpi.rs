@@ -0,0 +1,405 @@
+use alloc::vec::Vec;
+use core::{mem::size_of, ptr, slice};
+
+use spin::Once;
+use x86_64::PhysAddr;
+
+use crate::{info, memory, warn};
+
+// https://wiki.osdev.org/RSDP
+const RSDP_SIGNATURE: &[u8; 8] = b"RSD PTR ";
+const EBDA_POINTER: u64 = 0x40E;
+const EBDA_SEARCH_SIZE: u64 = 1024;
+const BIOS_AREA_START: u64 = 0xE0000;
+const BIOS_AREA_END: u64 = 0x100000;
+const RSDP_V1_SIZE: usize = 20;
+
+const MADT_SIGNATURE: &[u8; 4] = b"APIC";
+const FADT_SIGNATURE: &[u8; 4] = b"FACP";
+
+// Offsets of the FADT fields, https://wiki.osdev.org/FADT
+const FADT_DSDT: usize = 40;
+const FADT_SMI_COMMAND: usize = 48;
+const FADT_ACPI_ENABLE: usize = 52;
+const FADT_PM1A_CONTROL: usize = 64;
+const FADT_PM1B_CONTROL: usize = 68;
+const FADT_FLAGS: usize = 112;
+const FADT_RESET_REGISTER: usize = 116;
+const FADT_RESET_VALUE: usize = 128;
+const FADT_X_DSDT: usize = 140;
+
+const FADT_RESET_REGISTER_SUPPORTED: u32 = 1 << 10;
+
+const AML_NAME_OP: u8 = 0x08;
+const AML_PACKAGE_OP: u8 = 0x12;
+const AML_BYTE_PREFIX: u8 = 0x0A;
+const AML_ROOT_CHAR: u8 = b'\\';
+
+// https://wiki.osdev.org/MADT
+const MADT_LOCAL_APIC: u8 = 0;
+const MADT_IO_APIC: u8 = 1;
+const MADT_INTERRUPT_OVERRIDE: u8 = 2;
+const MADT_LOCAL_APIC_ADDRESS_OVERRIDE: u8 = 5;
+
+const LOCAL_APIC_ENABLED: u32 = 1 << 0;
+const LOCAL_APIC_ONLINE_CAPABLE: u32 = 1 << 1;
+
+// Physical addresses of every table listed in the RSDT or XSDT
+static TABLES: Once<Vec<PhysAddr>> = Once::new();
+
+#[repr(C, packed)]
+#[derive(Clone, Copy)]
+struct Rsdp {
+    signature: [u8; 8],
+    checksum: u8,
+    oem_id: [u8; 6],
+    revision: u8,
+    rsdt_address: u32,
+    // Only valid from revision 2
+    length: u32,
+    xsdt_address: u64,
+    extended_checksum: u8,
+    reserved: [u8; 3],
+}
+
+#[repr(C, packed)]
+#[derive(Clone, Copy)]
+pub struct SdtHeader {
+    pub signature: [u8; 4],
+    pub length: u32,
+    pub revision: u8,
+    checksum: u8,
+    oem_id: [u8; 6],
+    oem_table_id: [u8; 8],
+    oem_revision: u32,
+    creator_id: u32,
+    creator_revision: u32,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct IoApicInfo {
+    pub id: u8,
+    pub address: PhysAddr,
+    pub gsi_base: u32,
+}
+
+// An ISA IRQ that is not wired to the global system interrupt with the same number
+#[derive(Debug, Clone, Copy)]
+pub struct InterruptOverride {
+    pub irq: u8,
+    pub gsi: u32,
+    pub flags: u16,
+}
+
+#[derive(Debug, Clone)]
+pub struct Madt {
+    pub local_apic_address: PhysAddr,
+    // Local APIC ids of the usable processors
+    pub processors: Vec<u8>,
+    pub io_apics: Vec<IoApicInfo>,
+    pub overrides: Vec<InterruptOverride>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct GenericAddress {
+    pub address_space: u8,
+    pub address: u64,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Fadt {
+    pub dsdt: PhysAddr,
+    pub smi_command: u32,
+    pub acpi_enable: u8,
+    pub pm1a_control: u32,
+    pub pm1b_control: u32,
+    // Where to write the reset value to restart the computer
+    pub reset_register: Option<(GenericAddress, u8)>,
+}
+
+// Reads a value from physical memory, mapping it first if needed
+unsafe fn read_physical<T: Copy>(addr: PhysAddr) -> T {
+    let virt = memory::map_physical_region(addr, size_of::<T>());
+    ptr::read_unaligned(virt.as_ptr())
+}
+
+unsafe fn physical_bytes(addr: PhysAddr, len: usize) -> &'static [u8] {
+    let virt = memory::map_physical_region(addr, len);
+    slice::from_raw_parts(virt.as_ptr(), len)
+}
+
+fn checksum_ok(bytes: &[u8]) -> bool {
+    bytes.iter().fold(0u8, |sum, &b| sum.wrapping_add(b)) == 0
+}
+
+// Needs `memory::init` and the heap, does nothing if there are no ACPI tables
+pub fn init() {
+    let rsdp = match unsafe { find_rsdp() } {
+        Some(rsdp) => rsdp,
+        None => {
+            warn!("ACPI RSDP not found");
+            return;
+        }
+    };
+
+    let tables = unsafe { read_tables(&rsdp) };
+    info!("Found {} ACPI tables (revision {})", tables.len(), rsdp.revision);
+    TABLES.call_once(|| tables);
+}
+
+unsafe fn find_rsdp() -> Option<Rsdp> {
+    let ebda = (read_physical::<u16>(PhysAddr::new(EBDA_POINTER)) as u64) << 4;
+    let areas = [
+        (ebda, ebda + EBDA_SEARCH_SIZE),
+        (BIOS_AREA_START, BIOS_AREA_END),
+    ];
+
+    // The RSDP is always on a 16 byte boundary
+    for (start, end) in areas.into_iter().filter(|&(start, _)| start != 0) {
+        for addr in (start..end).step_by(16) {
+            let addr = PhysAddr::new(addr);
+            if physical_bytes(addr, RSDP_SIGNATURE.len()) != RSDP_SIGNATURE {
+                continue;
+            }
+
+            let rsdp: Rsdp = read_physical(addr);
+            let valid = match rsdp.revision {
+                0 => checksum_ok(physical_bytes(addr, RSDP_V1_SIZE)),
+                _ => checksum_ok(physical_bytes(addr, rsdp.length as usize)),
+            };
+            if valid {
+                return Some(rsdp);
+            }
+        }
+    }
+
+    None
+}
+
+unsafe fn read_tables(rsdp: &Rsdp) -> Vec<PhysAddr> {
+    // The XSDT has 64 bit pointers, the RSDT only 32 bit ones
+    let (root, entry_size) = match (rsdp.revision, rsdp.xsdt_address) {
+        (revision, xsdt) if revision >= 2 && xsdt != 0 => (PhysAddr::new(xsdt), 8),
+        _ => (PhysAddr::new(rsdp.rsdt_address as u64), 4),
+    };
+
+    let header = match read_table_header(root) {
+        Some(header) => header,
+        None => {
+            warn!("Invalid ACPI root table");
+            return Vec::new();
+        }
+    };
+
+    let entries = (header.length as usize - size_of::<SdtHeader>()) / entry_size;
+    let first_entry = root + size_of::<SdtHeader>();
+
+    (0..entries)
+        .map(|i| {
+            let entry = first_entry + i * entry_size;
+            match entry_size {
+                8 => PhysAddr::new(read_physical::<u64>(entry)),
+                _ => PhysAddr::new(read_physical::<u32>(entry) as u64),
+            }
+        })
+        .filter(|&table| read_table_header(table).is_some())
+        .collect()
+}
+
+// Only gives back headers of tables with a valid checksum
+unsafe fn read_table_header(addr: PhysAddr) -> Option<SdtHeader> {
+    let header: SdtHeader = read_physical(addr);
+    let length = header.length as usize;
+    let valid = length >= size_of::<SdtHeader>() && checksum_ok(physical_bytes(addr, length));
+    valid.then(|| header)
+}
+
+pub fn find_table(signature: &[u8; 4]) -> Option<PhysAddr> {
+    TABLES
+        .get()?
+        .iter()
+        .copied()
+        .find(|&addr| unsafe { read_physical::<[u8; 4]>(addr) } == *signature)
+}
+
+// The whole table, header included
+pub fn table_bytes(addr: PhysAddr) -> &'static [u8] {
+    unsafe {
+        let header: SdtHeader = read_physical(addr);
+        physical_bytes(addr, header.length as usize)
+    }
+}
+
+pub fn madt() -> Option<Madt> {
+    let bytes = table_bytes(find_table(MADT_SIGNATURE)?);
+    Some(parse_madt(bytes))
+}
+
+pub fn fadt() -> Option<Fadt> {
+    let bytes = table_bytes(find_table(FADT_SIGNATURE)?);
+    parse_fadt(bytes)
+}
+
+fn parse_fadt(bytes: &[u8]) -> Option<Fadt> {
+    let read_u8 = |i: usize| bytes.get(i).copied();
+    let read_u32 = |i: usize| Some(u32::from_le_bytes(bytes.get(i..i + 4)?.try_into().unwrap()));
+    let read_u64 = |i: usize| Some(u64::from_le_bytes(bytes.get(i..i + 8)?.try_into().unwrap()));
+
+    // The 64 bit address replaces the 32 bit one when present
+    let dsdt = match read_u64(FADT_X_DSDT) {
+        Some(x_dsdt) if x_dsdt != 0 => x_dsdt,
+        _ => read_u32(FADT_DSDT)? as u64,
+    };
+
+    let reset_supported = read_u32(FADT_FLAGS).unwrap_or(0) & FADT_RESET_REGISTER_SUPPORTED != 0;
+    let reset_register = match reset_supported {
+        true => Some((
+            GenericAddress {
+                address_space: read_u8(FADT_RESET_REGISTER)?,
+                address: read_u64(FADT_RESET_REGISTER + 4)?,
+            },
+            read_u8(FADT_RESET_VALUE)?,
+        )),
+        false => None,
+    };
+
+    Some(Fadt {
+        dsdt: PhysAddr::new(dsdt),
+        smi_command: read_u32(FADT_SMI_COMMAND)?,
+        acpi_enable: read_u8(FADT_ACPI_ENABLE)?,
+        pm1a_control: read_u32(FADT_PM1A_CONTROL)?,
+        pm1b_control: read_u32(FADT_PM1B_CONTROL)?,
+        reset_register,
+    })
+}
+
+// SLP_TYPa and SLP_TYPb values for the soft off state, found in the \_S5 package of the DSDT
+// without a full AML interpreter, https://forum.osdev.org/viewtopic.php?t=16990
+pub fn parse_s5(dsdt: &[u8]) -> Option<(u8, u8)> {
+    let start = dsdt.windows(4).position(|name| name == b"_S5_")?;
+
+    // Has to be a definition, either "Name(_S5_" or "Name(\_S5_"
+    let named = match start {
+        0 => false,
+        1 => dsdt[0] == AML_NAME_OP,
+        _ => {
+            dsdt[start - 1] == AML_NAME_OP
+                || (dsdt[start - 1] == AML_ROOT_CHAR && dsdt[start - 2] == AML_NAME_OP)
+        }
+    };
+    if !named {
+        return None;
+    }
+
+    let mut bytes = dsdt[start + 4..].iter().copied();
+    if bytes.next()? != AML_PACKAGE_OP {
+        return None;
+    }
+
+    // The top 2 bits of the package length tell how many more length bytes follow,
+    // the byte after them is the number of elements
+    let length_bytes = (bytes.next()? >> 6) as usize;
+    bytes.nth(length_bytes)?;
+
+    // Small values are encoded directly as ZeroOp or OneOp, others after a byte prefix
+    let mut next_value = || match bytes.next()? {
+        AML_BYTE_PREFIX => bytes.next(),
+        value => Some(value),
+    };
+
+    Some((next_value()?, next_value()?))
+}
+
+fn parse_madt(bytes: &[u8]) -> Madt {
+    let read_u16 = |i: usize| u16::from_le_bytes([bytes[i], bytes[i + 1]]);
+    let read_u32 = |i: usize| u32::from_le_bytes(bytes[i..i + 4].try_into().unwrap());
+    let read_u64 = |i: usize| u64::from_le_bytes(bytes[i..i + 8].try_into().unwrap());
+
+    let header_size = size_of::<SdtHeader>();
+    let mut madt = Madt {
+        local_apic_address: PhysAddr::new(read_u32(header_size) as u64),
+        processors: Vec::new(),
+        io_apics: Vec::new(),
+        overrides: Vec::new(),
+    };
+
+    // Entries start after the local APIC address and the flags
+    let mut i = header_size + 8;
+    while i + 2 <= bytes.len() {
+        let (kind, len) = (bytes[i], bytes[i + 1] as usize);
+        if len < 2 || i + len > bytes.len() {
+            break;
+        }
+
+        match kind {
+            MADT_LOCAL_APIC if len >= 8 => {
+                let flags = read_u32(i + 4);
+                if flags & (LOCAL_APIC_ENABLED | LOCAL_APIC_ONLINE_CAPABLE) != 0 {
+                    madt.processors.push(bytes[i + 3]);
+                }
+            }
+            MADT_IO_APIC if len >= 12 => madt.io_apics.push(IoApicInfo {
+                id: bytes[i + 2],
+                address: PhysAddr::new(read_u32(i + 4) as u64),
+                gsi_base: read_u32(i + 8),
+            }),
+            MADT_INTERRUPT_OVERRIDE if len >= 10 => madt.overrides.push(InterruptOverride {
+                irq: bytes[i + 3],
+                gsi: read_u32(i + 4),
+                flags: read_u16(i + 8),
+            }),
+            MADT_LOCAL_APIC_ADDRESS_OVERRIDE if len >= 12 => {
+                madt.local_apic_address = PhysAddr::new(read_u64(i + 4));
+            }
+            _ => {}
+        }
+
+        i += len;
+    }
+
+    madt
+}
+
+#[test_case]
+fn test_parse_madt() {
+    let mut bytes = alloc::vec![0u8; size_of::<SdtHeader>()];
+    bytes.extend_from_slice(&0xFEE0_0000u32.to_le_bytes());
+    bytes.extend_from_slice(&1u32.to_le_bytes());
+    // Processor 0 with APIC id 0, enabled
+    bytes.extend_from_slice(&[MADT_LOCAL_APIC, 8, 0, 0, 1, 0, 0, 0]);
+    // Disabled processor
+    bytes.extend_from_slice(&[MADT_LOCAL_APIC, 8, 1, 1, 0, 0, 0, 0]);
+    // I/O APIC 2 at 0xFEC00000 handling GSIs from 0
+    bytes.extend_from_slice(&[MADT_IO_APIC, 12, 2, 0, 0x00, 0x00, 0xC0, 0xFE, 0, 0, 0, 0]);
+    // IRQ 0 wired to GSI 2
+    bytes.extend_from_slice(&[MADT_INTERRUPT_OVERRIDE, 10, 0, 0, 2, 0, 0, 0, 0, 0]);
+
+    let madt = parse_madt(&bytes);
+    assert_eq!(madt.local_apic_address, PhysAddr::new(0xFEE0_0000));
+    assert_eq!(madt.processors, [0]);
+    assert_eq!(madt.io_apics.len(), 1);
+    assert_eq!(madt.io_apics[0].id, 2);
+    assert_eq!(madt.io_apics[0].address, PhysAddr::new(0xFEC0_0000));
+    assert_eq!(madt.overrides[0].irq, 0);
+    assert_eq!(madt.overrides[0].gsi, 2);
+}
+
+#[test_case]
+fn test_parse_s5() {
+    // Name (_S5, Package (0x04) { 0x05, 0x05, Zero, Zero })
+    let dsdt = [
+        0x10, AML_NAME_OP, b'_', b'S', b'5', b'_', AML_PACKAGE_OP, 0x0A, 0x04, AML_BYTE_PREFIX,
+        0x05, AML_BYTE_PREFIX, 0x05, 0x00, 0x00,
+    ];
+    assert_eq!(parse_s5(&dsdt), Some((5, 5)));
+
+    // Name (\_S5, Package (0x04) { Zero, Zero, Zero, Zero })
+    let dsdt = [
+        AML_NAME_OP, AML_ROOT_CHAR, b'_', b'S', b'5', b'_', AML_PACKAGE_OP, 0x06, 0x04, 0x00,
+        0x00, 0x00, 0x00,
+    ];
+    assert_eq!(parse_s5(&dsdt), Some((0, 0)));
+
+    // A method call, not the definition
+    assert_eq!(parse_s5(b"\x14_S5_"), None);
+}
@@ -0,0 +1,220 @@
+use core::{
+    alloc::Layout,
+    fmt, mem,
+    ptr::{self, NonNull},
+};
+
+use linked_list_allocator::Heap;
+
+// Block sizes must be powers of 2 since they are also used as the block alignment
+pub const BLOCK_SIZES: &[usize] = &[8, 16, 32, 64, 128, 256, 512, 1024, 2048];
+pub const SIZE_CLASSES: usize = BLOCK_SIZES.len();
+
+struct ListNode {
+    next: Option<&'static mut ListNode>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct HeapStats {
+    pub heap_size: usize,
+    // Bytes requested by the live allocations
+    pub live_bytes: usize,
+    pub peak_bytes: usize,
+    // Bytes taken from the fallback allocator, including the blocks kept in the free lists
+    pub reserved_bytes: usize,
+    pub live_blocks: [usize; SIZE_CLASSES],
+    pub free_blocks: [usize; SIZE_CLASSES],
+    // Allocations too big for a block, served by the fallback allocator
+    pub large_allocations: usize,
+}
+
+// Small allocations are served from per-size free lists, bigger ones from a linked list heap
+pub struct FixedSizeBlockAllocator {
+    list_heads: [Option<&'static mut ListNode>; SIZE_CLASSES],
+    fallback_allocator: Heap,
+    live_bytes: usize,
+    peak_bytes: usize,
+    live_blocks: [usize; SIZE_CLASSES],
+    free_blocks: [usize; SIZE_CLASSES],
+    large_allocations: usize,
+}
+
+impl FixedSizeBlockAllocator {
+    pub const fn new() -> Self {
+        const EMPTY: Option<&'static mut ListNode> = None;
+        Self {
+            list_heads: [EMPTY; SIZE_CLASSES],
+            fallback_allocator: Heap::empty(),
+            live_bytes: 0,
+            peak_bytes: 0,
+            live_blocks: [0; SIZE_CLASSES],
+            free_blocks: [0; SIZE_CLASSES],
+            large_allocations: 0,
+        }
+    }
+
+    // The memory range must be unused and valid for the whole lifetime of the allocator
+    pub unsafe fn init(&mut self, heap_start: usize, heap_size: usize) {
+        self.fallback_allocator.init(heap_start, heap_size);
+    }
+
+    // The memory right after the end of the heap must be mapped and unused
+    pub unsafe fn extend(&mut self, by: usize) {
+        self.fallback_allocator.extend(by);
+    }
+
+    pub fn stats(&self) -> HeapStats {
+        HeapStats {
+            heap_size: self.fallback_allocator.size(),
+            live_bytes: self.live_bytes,
+            peak_bytes: self.peak_bytes,
+            reserved_bytes: self.fallback_allocator.used(),
+            live_blocks: self.live_blocks,
+            free_blocks: self.free_blocks,
+            large_allocations: self.large_allocations,
+        }
+    }
+
+    fn list_index(layout: &Layout) -> Option<usize> {
+        let required_block_size = layout.size().max(layout.align());
+        BLOCK_SIZES.iter().position(|&s| s >= required_block_size)
+    }
+
+    fn fallback_alloc(&mut self, layout: Layout) -> *mut u8 {
+        match self.fallback_allocator.allocate_first_fit(layout) {
+            Ok(ptr) => ptr.as_ptr(),
+            Err(_) => ptr::null_mut(),
+        }
+    }
+
+    pub fn allocate(&mut self, layout: Layout) -> *mut u8 {
+        let ptr = match Self::list_index(&layout) {
+            Some(index) => {
+                let ptr = match self.list_heads[index].take() {
+                    Some(node) => {
+                        self.list_heads[index] = node.next.take();
+                        self.free_blocks[index] -= 1;
+                        node as *mut ListNode as *mut u8
+                    }
+                    None => {
+                        let block_size = BLOCK_SIZES[index];
+                        let block_align = block_size;
+                        let layout = Layout::from_size_align(block_size, block_align).unwrap();
+                        self.fallback_alloc(layout)
+                    }
+                };
+                if !ptr.is_null() {
+                    self.live_blocks[index] += 1;
+                }
+                ptr
+            }
+            None => {
+                let ptr = self.fallback_alloc(layout);
+                if !ptr.is_null() {
+                    self.large_allocations += 1;
+                }
+                ptr
+            }
+        };
+
+        if !ptr.is_null() {
+            self.live_bytes += layout.size();
+            self.peak_bytes = self.peak_bytes.max(self.live_bytes);
+        }
+
+        ptr
+    }
+
+    // The pointer must come from `allocate` with the same layout
+    pub unsafe fn deallocate(&mut self, ptr: *mut u8, layout: Layout) {
+        match Self::list_index(&layout) {
+            Some(index) => {
+                // The block is kept in its free list instead of going back to the fallback
+                assert!(mem::size_of::<ListNode>() <= BLOCK_SIZES[index]);
+                assert!(mem::align_of::<ListNode>() <= BLOCK_SIZES[index]);
+
+                let new_node = ListNode {
+                    next: self.list_heads[index].take(),
+                };
+                let new_node_ptr = ptr as *mut ListNode;
+                new_node_ptr.write(new_node);
+                self.list_heads[index] = Some(&mut *new_node_ptr);
+
+                self.live_blocks[index] -= 1;
+                self.free_blocks[index] += 1;
+            }
+            None => {
+                let ptr = NonNull::new(ptr).unwrap();
+                self.fallback_allocator.deallocate(ptr, layout);
+                self.large_allocations -= 1;
+            }
+        }
+
+        self.live_bytes -= layout.size();
+    }
+}
+
+impl HeapStats {
+    // Percentage of the reserved memory not used by live allocations,
+    // either from rounding up to a block size or from blocks sitting in the free lists
+    pub fn fragmentation(&self) -> usize {
+        match self.reserved_bytes {
+            0 => 0,
+            reserved => reserved.saturating_sub(self.live_bytes) * 100 / reserved,
+        }
+    }
+}
+
+impl fmt::Display for HeapStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "heap {}/{} KiB reserved, {} KiB live, {} KiB peak, {}% fragmented, {} large",
+            self.reserved_bytes / 1024,
+            self.heap_size / 1024,
+            self.live_bytes / 1024,
+            self.peak_bytes / 1024,
+            self.fragmentation(),
+            self.large_allocations
+        )?;
+
+        for (i, size) in BLOCK_SIZES.iter().enumerate() {
+            write!(f, ", {}B: {}+{}", size, self.live_blocks[i], self.free_blocks[i])?;
+        }
+
+        Ok(())
+    }
+}
+
+#[test_case]
+fn test_block_reuse() {
+    #[repr(align(4096))]
+    struct TestHeap([u8; 4096]);
+    static mut HEAP: TestHeap = TestHeap([0; 4096]);
+
+    let mut allocator = FixedSizeBlockAllocator::new();
+    unsafe { allocator.init(HEAP.0.as_mut_ptr() as usize, HEAP.0.len()) };
+
+    let layout = Layout::from_size_align(24, 8).unwrap();
+    let first = allocator.allocate(layout);
+    assert!(!first.is_null());
+    assert_eq!(allocator.stats().live_blocks[2], 1);
+    assert_eq!(allocator.stats().live_bytes, 24);
+
+    unsafe { allocator.deallocate(first, layout) };
+    assert_eq!(allocator.stats().free_blocks[2], 1);
+
+    // The freed block is handed out again
+    let second = allocator.allocate(layout);
+    assert_eq!(first, second);
+    assert_eq!(allocator.stats().free_blocks[2], 0);
+    assert_eq!(allocator.stats().peak_bytes, 24);
+
+    // Too big for a block, goes to the fallback
+    let large = Layout::from_size_align(3000, 8).unwrap();
+    let ptr = allocator.allocate(large);
+    assert!(!ptr.is_null());
+    assert_eq!(allocator.stats().large_allocations, 1);
+    unsafe { allocator.deallocate(ptr, large) };
+    assert_eq!(allocator.stats().large_allocations, 0);
+}
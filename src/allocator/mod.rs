@@ -0,0 +1,211 @@
+use core::{
+    alloc::{GlobalAlloc, Layout},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use spin::{Mutex, MutexGuard};
+use x86_64::{
+    structures::paging::{
+        mapper::MapToError, FrameAllocator, FrameDeallocator, Mapper, Page, PageTableFlags,
+        Size4KiB,
+    },
+    VirtAddr,
+};
+
+use crate::{backtrace, memory};
+
+use self::fixed_size_block::{FixedSizeBlockAllocator, HeapStats};
+
+pub mod fixed_size_block;
+
+#[global_allocator]
+static ALLOCATOR: Locked<FixedSizeBlockAllocator> = Locked::new(FixedSizeBlockAllocator::new());
+
+pub const HEAP_START: usize = 0x_4444_4444_0000;
+pub const HEAP_INITIAL_SIZE: usize = 1024 * 1024; // 1 MiB
+
+// Smallest amount the heap grows by, so small allocations don't each map a page
+const HEAP_GROWTH: usize = 256 * 1024; // 256 KiB
+
+const PAGE_SIZE: usize = 4096;
+
+// Set from the memory map, the heap never grows past it
+static HEAP_LIMIT: AtomicUsize = AtomicUsize::new(HEAP_INITIAL_SIZE);
+
+// Needs `memory::init` to have been called
+pub fn init_heap() -> Result<(), MapToError<Size4KiB>> {
+    // Leaving a quarter of the physical memory for page tables and other mappings
+    let usable_bytes = memory::frame_allocator().free_frames() * PAGE_SIZE;
+    let limit = (usable_bytes / 4 * 3).max(HEAP_INITIAL_SIZE);
+    HEAP_LIMIT.store(limit, Ordering::Relaxed);
+
+    map_heap(HEAP_START, HEAP_INITIAL_SIZE)?;
+
+    unsafe {
+        ALLOCATOR.lock().init(HEAP_START, HEAP_INITIAL_SIZE);
+    }
+
+    Ok(())
+}
+
+#[alloc_error_handler]
+fn alloc_error_handler(layout: Layout) -> ! {
+    match caller() {
+        Some((name, address)) => panic!(
+            "Out of memory allocating {} bytes aligned to {} in {} ({:#x}), {}",
+            layout.size(),
+            layout.align(),
+            name,
+            address,
+            stats()
+        ),
+        None => panic!(
+            "Out of memory allocating {} bytes aligned to {}, {}",
+            layout.size(),
+            layout.align(),
+            stats()
+        ),
+    }
+}
+
+// The first function of the backtrace outside the allocation code, needs the symbol table
+fn caller() -> Option<(&'static str, u64)> {
+    const ALLOCATION_CODE: [&str; 6] = [
+        "alloc::",
+        "<alloc::",
+        "core::",
+        "<core::",
+        "__r",
+        "bmc_os::allocator::",
+    ];
+
+    let mut caller = None;
+    backtrace::walk(|address| {
+        if caller.is_some() {
+            return;
+        }
+        if let Some((name, _)) = backtrace::symbolize(address - 1) {
+            if !ALLOCATION_CODE.iter().any(|prefix| name.starts_with(prefix)) {
+                caller = Some((name, address));
+            }
+        }
+    });
+    caller
+}
+
+pub fn stats() -> HeapStats {
+    ALLOCATOR.lock().stats()
+}
+
+pub fn heap_limit() -> usize {
+    HEAP_LIMIT.load(Ordering::Relaxed)
+}
+
+fn page_range(start: usize, size: usize) -> impl Iterator<Item = Page> {
+    let start = VirtAddr::new(start as u64);
+    let end = start + size - 1u64;
+    Page::range_inclusive(Page::containing_address(start), Page::containing_address(end))
+}
+
+// Either all pages of the range are mapped, or none of them
+fn map_heap(start: usize, size: usize) -> Result<(), MapToError<Size4KiB>> {
+    let mut mapper = memory::mapper();
+    let mut frame_allocator = memory::frame_allocator();
+    let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+
+    for (mapped, page) in page_range(start, size).enumerate() {
+        let res = match frame_allocator.allocate_frame() {
+            Some(frame) => {
+                let res = unsafe { mapper.map_to(page, frame, flags, &mut *frame_allocator) };
+                if res.is_err() {
+                    unsafe { frame_allocator.deallocate_frame(frame) };
+                }
+                res
+            }
+            None => Err(MapToError::FrameAllocationFailed),
+        };
+
+        match res {
+            Ok(flush) => flush.flush(),
+            Err(err) => {
+                for page in page_range(start, size).take(mapped) {
+                    if let Ok((frame, flush)) = mapper.unmap(page) {
+                        flush.flush();
+                        unsafe { frame_allocator.deallocate_frame(frame) };
+                    }
+                }
+                return Err(err);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// Maps more pages after the end of the heap so that the allocation can succeed
+fn grow_heap(allocator: &mut FixedSizeBlockAllocator, layout: Layout) -> bool {
+    // The extra alignment covers the worst case of the fallback allocator,
+    // when the end of the heap is not free
+    let required = layout.size() + layout.align();
+    let by = (required.max(HEAP_GROWTH) + PAGE_SIZE - 1) / PAGE_SIZE * PAGE_SIZE;
+
+    let heap_size = allocator.stats().heap_size;
+    if heap_size + by > heap_limit() || map_heap(HEAP_START + heap_size, by).is_err() {
+        return false;
+    }
+
+    unsafe { allocator.extend(by) };
+    true
+}
+
+// Wrapper to be able to implement `GlobalAlloc` on allocators behind a spinlock
+pub struct Locked<A> {
+    inner: Mutex<A>,
+}
+
+impl<A> Locked<A> {
+    pub const fn new(inner: A) -> Self {
+        Locked {
+            inner: Mutex::new(inner),
+        }
+    }
+
+    pub fn lock(&self) -> MutexGuard<A> {
+        self.inner.lock()
+    }
+}
+
+unsafe impl GlobalAlloc for Locked<FixedSizeBlockAllocator> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let mut allocator = self.lock();
+
+        match allocator.allocate(layout) {
+            ptr if ptr.is_null() && grow_heap(&mut allocator, layout) => allocator.allocate(layout),
+            ptr => ptr,
+        }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.lock().deallocate(ptr, layout)
+    }
+}
+
+#[test_case]
+fn test_allocation_failure_is_recoverable() {
+    use alloc::vec::Vec;
+
+    let mut v: Vec<u8> = Vec::new();
+    assert!(v.try_reserve_exact(heap_limit() + 1).is_err());
+    assert!(v.try_reserve_exact(1024).is_ok());
+}
+
+#[test_case]
+fn test_heap_grows() {
+    use alloc::vec::Vec;
+
+    let before = stats().heap_size;
+    let big: Vec<u8> = Vec::with_capacity(2 * HEAP_INITIAL_SIZE);
+    assert!(stats().heap_size > before);
+    assert!(stats().heap_size <= heap_limit());
+    drop(big);
+}
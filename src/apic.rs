@@ -0,0 +1,222 @@
+use alloc::vec::Vec;
+use core::{arch::x86_64::__cpuid, ptr, time::Duration};
+
+use spin::Once;
+use x86_64::{
+    instructions::{interrupts, port::Port},
+    VirtAddr,
+};
+
+use crate::{
+    acpi::{self, IoApicInfo, Madt},
+    interrupts::InterruptIndex,
+    memory, time,
+};
+
+// https://wiki.osdev.org/APIC
+const LAPIC_ID: usize = 0x20;
+const LAPIC_TASK_PRIORITY: usize = 0x80;
+const LAPIC_EOI: usize = 0xB0;
+const LAPIC_SPURIOUS: usize = 0xF0;
+const LAPIC_LVT_TIMER: usize = 0x320;
+const LAPIC_TIMER_INITIAL_COUNT: usize = 0x380;
+const LAPIC_TIMER_CURRENT_COUNT: usize = 0x390;
+const LAPIC_TIMER_DIVIDE: usize = 0x3E0;
+
+const LAPIC_SOFTWARE_ENABLE: u32 = 1 << 8;
+const LVT_MASKED: u32 = 1 << 16;
+const TIMER_PERIODIC: u32 = 1 << 17;
+const TIMER_DIVIDE_BY_16: u32 = 0b0011;
+
+pub const SPURIOUS_VECTOR: u8 = 0xFF;
+
+// https://wiki.osdev.org/IOAPIC
+const IOAPIC_REGISTER_SELECT: usize = 0x00;
+const IOAPIC_WINDOW: usize = 0x10;
+const IOAPIC_VERSION: u32 = 0x01;
+const IOAPIC_REDIRECTION_TABLE: u32 = 0x10;
+
+const REDIRECTION_ACTIVE_LOW: u64 = 1 << 13;
+const REDIRECTION_LEVEL_TRIGGERED: u64 = 1 << 15;
+
+// Flags of the MADT interrupt source overrides
+const OVERRIDE_POLARITY_MASK: u16 = 0b11;
+const OVERRIDE_ACTIVE_LOW: u16 = 0b11;
+const OVERRIDE_TRIGGER_MASK: u16 = 0b11 << 2;
+const OVERRIDE_LEVEL_TRIGGERED: u16 = 0b11 << 2;
+
+const PIC_1_DATA: u16 = 0x21;
+const PIC_2_DATA: u16 = 0xA1;
+
+const KEYBOARD_IRQ: u8 = 1;
+const MOUSE_IRQ: u8 = 12;
+
+const CALIBRATION_TIME: Duration = Duration::from_millis(10);
+
+static LOCAL_APIC: Once<LocalApic> = Once::new();
+
+pub struct LocalApic {
+    base: VirtAddr,
+}
+
+struct IoApic {
+    base: VirtAddr,
+    gsi_base: u32,
+    entries: u32,
+}
+
+impl LocalApic {
+    unsafe fn read(&self, register: usize) -> u32 {
+        ptr::read_volatile((self.base + register).as_ptr())
+    }
+
+    unsafe fn write(&self, register: usize, value: u32) {
+        ptr::write_volatile((self.base + register).as_mut_ptr(), value)
+    }
+
+    pub fn id(&self) -> u8 {
+        unsafe { (self.read(LAPIC_ID) >> 24) as u8 }
+    }
+
+    pub fn end_of_interrupt(&self) {
+        unsafe { self.write(LAPIC_EOI, 0) }
+    }
+
+    unsafe fn enable(&self) {
+        self.write(LAPIC_TASK_PRIORITY, 0);
+        self.write(LAPIC_SPURIOUS, LAPIC_SOFTWARE_ENABLE | SPURIOUS_VECTOR as u32);
+    }
+
+    // Counts the timer does during one tick, measured with the PIT so interrupts need to be enabled
+    unsafe fn calibrate_timer(&self) -> u32 {
+        self.write(LAPIC_TIMER_DIVIDE, TIMER_DIVIDE_BY_16);
+        self.write(LAPIC_LVT_TIMER, LVT_MASKED);
+        self.write(LAPIC_TIMER_INITIAL_COUNT, u32::MAX);
+
+        time::sleep(CALIBRATION_TIME);
+
+        let elapsed = u32::MAX - self.read(LAPIC_TIMER_CURRENT_COUNT);
+        self.write(LAPIC_TIMER_INITIAL_COUNT, 0);
+
+        let tick = time::ticks_to_duration(1);
+        (elapsed as u128 * tick.as_nanos() / CALIBRATION_TIME.as_nanos()) as u32
+    }
+
+    unsafe fn start_timer(&self, count: u32) {
+        self.write(LAPIC_TIMER_DIVIDE, TIMER_DIVIDE_BY_16);
+        self.write(LAPIC_LVT_TIMER, TIMER_PERIODIC | InterruptIndex::Timer.as_u8() as u32);
+        self.write(LAPIC_TIMER_INITIAL_COUNT, count);
+    }
+}
+
+impl IoApic {
+    unsafe fn new(info: &IoApicInfo) -> Self {
+        let mut io_apic = Self {
+            base: memory::map_physical_region(info.address, 0x20),
+            gsi_base: info.gsi_base,
+            entries: 0,
+        };
+        io_apic.entries = ((io_apic.read(IOAPIC_VERSION) >> 16) & 0xFF) + 1;
+        io_apic
+    }
+
+    unsafe fn read(&self, register: u32) -> u32 {
+        ptr::write_volatile((self.base + IOAPIC_REGISTER_SELECT).as_mut_ptr(), register);
+        ptr::read_volatile((self.base + IOAPIC_WINDOW).as_ptr())
+    }
+
+    unsafe fn write(&self, register: u32, value: u32) {
+        ptr::write_volatile((self.base + IOAPIC_REGISTER_SELECT).as_mut_ptr(), register);
+        ptr::write_volatile((self.base + IOAPIC_WINDOW).as_mut_ptr(), value)
+    }
+
+    fn handles(&self, gsi: u32) -> bool {
+        (self.gsi_base..self.gsi_base + self.entries).contains(&gsi)
+    }
+
+    unsafe fn route(&self, gsi: u32, entry: u64) {
+        let register = IOAPIC_REDIRECTION_TABLE + (gsi - self.gsi_base) * 2;
+        self.write(register, entry as u32);
+        self.write(register + 1, (entry >> 32) as u32);
+    }
+}
+
+pub fn local_apic() -> Option<&'static LocalApic> {
+    LOCAL_APIC.get()
+}
+
+pub fn is_supported() -> bool {
+    unsafe { __cpuid(1).edx & (1 << 9) != 0 }
+}
+
+// Switches from the 8259 PIC to the local APIC and I/O APIC, which stay unused on failure.
+// Needs `acpi::init`, and interrupts enabled to calibrate the APIC timer
+pub fn init() -> Result<(), &'static str> {
+    if !is_supported() {
+        return Err("the CPU has no APIC");
+    }
+
+    let madt = acpi::madt().ok_or("no ACPI MADT")?;
+    if madt.io_apics.is_empty() {
+        return Err("no I/O APIC");
+    }
+
+    let local_apic = LocalApic {
+        base: memory::map_physical_region(madt.local_apic_address, 0x400),
+    };
+
+    unsafe {
+        let timer_count = local_apic.calibrate_timer();
+        if timer_count == 0 {
+            return Err("the APIC timer is not running");
+        }
+
+        let io_apics: Vec<IoApic> = madt.io_apics.iter().map(|info| IoApic::new(info)).collect();
+
+        interrupts::without_interrupts(|| {
+            disable_pic();
+            local_apic.enable();
+
+            let destination = local_apic.id();
+            let routes = [
+                (KEYBOARD_IRQ, InterruptIndex::Keyboard),
+                (MOUSE_IRQ, InterruptIndex::Mouse),
+            ];
+            for (irq, index) in routes {
+                route_isa_irq(&madt, &io_apics, irq, index.as_u8(), destination);
+            }
+
+            local_apic.start_timer(timer_count);
+            LOCAL_APIC.call_once(|| local_apic);
+        });
+    }
+
+    Ok(())
+}
+
+unsafe fn disable_pic() {
+    Port::<u8>::new(PIC_1_DATA).write(0xFF);
+    Port::<u8>::new(PIC_2_DATA).write(0xFF);
+}
+
+// ISA IRQs are identity mapped to global system interrupts unless the MADT overrides them
+unsafe fn route_isa_irq(madt: &Madt, io_apics: &[IoApic], irq: u8, vector: u8, destination: u8) {
+    let (gsi, flags) = madt
+        .overrides
+        .iter()
+        .find(|o| o.irq == irq)
+        .map(|o| (o.gsi, o.flags))
+        .unwrap_or((irq as u32, 0));
+
+    let mut entry = vector as u64 | (destination as u64) << 56;
+    if flags & OVERRIDE_POLARITY_MASK == OVERRIDE_ACTIVE_LOW {
+        entry |= REDIRECTION_ACTIVE_LOW;
+    }
+    if flags & OVERRIDE_TRIGGER_MASK == OVERRIDE_LEVEL_TRIGGERED {
+        entry |= REDIRECTION_LEVEL_TRIGGERED;
+    }
+
+    if let Some(io_apic) = io_apics.iter().find(|io_apic| io_apic.handles(gsi)) {
+        io_apic.route(gsi, entry);
+    }
+}
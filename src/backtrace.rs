@@ -0,0 +1,126 @@
+use core::{arch::asm, fmt, mem::size_of, str};
+
+use spin::Once;
+use x86_64::VirtAddr;
+
+use crate::gdt;
+
+// Generated by build.rs, lines of "<hex address> <name>" sorted by address and padded with zeros
+static SYMBOLS: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/symbols.bin"));
+
+const MAX_FRAMES: usize = 32;
+
+// Only used when it matches the running kernel, see `table`
+static TABLE: Once<Option<&'static str>> = Once::new();
+
+// What `rbp` points to when the kernel is built with frame pointers
+#[repr(C)]
+struct Frame {
+    previous: *const Frame,
+    return_address: u64,
+}
+
+// Calls `f` with the return address of every frame, starting from the caller
+#[inline(never)]
+pub fn walk(mut f: impl FnMut(u64)) {
+    let mut frame: *const Frame;
+    unsafe { asm!("mov {}, rbp", out(reg) frame) };
+
+    for _ in 0..MAX_FRAMES {
+        // A corrupted frame pointer must not fault while already reporting a crash
+        let address = VirtAddr::new(frame as u64);
+        let end = address + size_of::<Frame>();
+        let valid = address.is_aligned(8u64)
+            && gdt::is_stack_address(address)
+            && gdt::is_stack_address(end - 1u64);
+        if !valid {
+            return;
+        }
+
+        let Frame {
+            previous,
+            return_address,
+        } = unsafe { frame.read() };
+
+        if return_address == 0 {
+            return;
+        }
+        f(return_address);
+
+        // The stack grows down, so the previous frames are always higher
+        if previous <= frame {
+            return;
+        }
+        frame = previous;
+    }
+}
+
+pub fn print(f: &mut impl fmt::Write) -> fmt::Result {
+    writeln!(f, "Backtrace:")?;
+
+    let mut res = Ok(());
+    walk(|address| {
+        // The return address is after the call, which could be the start of the next function
+        res = res.and_then(|_| match symbolize(address - 1) {
+            Some((name, start)) => writeln!(f, "{:#x} {}+{:#x}", address, name, address - start),
+            None => writeln!(f, "{:#x}", address),
+        });
+    });
+    res
+}
+
+// Name and start address of the function containing the address
+#[inline(never)]
+pub fn symbolize(address: u64) -> Option<(&'static str, u64)> {
+    lookup(table()?, address)
+}
+
+// The symbols come from an earlier build, which may have been of different code. They are
+// only trusted when they have this function at the address it is running from
+fn table() -> Option<&'static str> {
+    *TABLE.call_once(|| {
+        let len = SYMBOLS.iter().position(|&b| b == 0).unwrap_or(SYMBOLS.len());
+        let table = str::from_utf8(&SYMBOLS[..len]).ok()?;
+
+        let address = symbolize as fn(u64) -> Option<(&'static str, u64)> as u64;
+        let matches = table.lines().any(|line| match line.split_once(' ') {
+            Some((start, "bmc_os::backtrace::symbolize")) => {
+                u64::from_str_radix(start, 16) == Ok(address)
+            }
+            _ => false,
+        });
+        matches.then_some(table)
+    })
+}
+
+fn lookup(table: &str, address: u64) -> Option<(&str, u64)> {
+    let mut found = None;
+
+    for line in table.lines() {
+        let (start, name) = line.split_once(' ')?;
+        let start = u64::from_str_radix(start, 16).ok()?;
+        if start > address {
+            break;
+        }
+        found = Some((name, start));
+    }
+
+    found
+}
+
+#[test_case]
+fn test_lookup() {
+    let table = "1000 first\n1040 second\n2000 third\n";
+
+    assert_eq!(lookup(table, 0x0fff), None);
+    assert_eq!(lookup(table, 0x1000), Some(("first", 0x1000)));
+    assert_eq!(lookup(table, 0x1fff), Some(("second", 0x1040)));
+    assert_eq!(lookup(table, 0x3000), Some(("third", 0x2000)));
+}
+
+#[test_case]
+fn test_walk() {
+    let mut frames = 0;
+    walk(|_| frames += 1);
+    assert!(frames > 0);
+}
@@ -0,0 +1,219 @@
+use core::{
+    fmt::{self, Write},
+    iter,
+    panic::PanicInfo,
+};
+
+use vga::colors::Color16;
+use x86_64::{
+    instructions::{hlt, interrupts},
+    registers::control::{Cr2, Cr3},
+    structures::idt::{InterruptStackFrame, PageFaultErrorCode},
+};
+
+use crate::{
+    backtrace,
+    display::{
+        self,
+        color::Color256,
+        graphics::{clear_buffer, draw_text, flush_buffer, CHAR_HEIGHT, CHAR_WIDTH, HEIGHT, WIDTH},
+        set_graphics_color,
+    },
+    error, print, serial, serial_print, set_text_color,
+};
+
+const COLUMNS: usize = WIDTH / CHAR_WIDTH;
+const ROWS: usize = HEIGHT / CHAR_HEIGHT;
+
+#[derive(Debug, Clone, Copy)]
+pub enum ErrorCode {
+    Raw(u64),
+    // Segment selector error code, pushed by #TS, #NP, #SS and #GP
+    Selector(u64),
+    PageFault(PageFaultErrorCode),
+}
+
+// Writes to the serial port and to the screen, in whichever mode is active
+struct CrashWriter {
+    graphics: bool,
+    column: usize,
+    row: usize,
+}
+
+impl CrashWriter {
+    fn new(title: &str) -> Self {
+        interrupts::disable();
+
+        // The code that crashed could have been holding them, and it will never run again
+        unsafe {
+            display::force_unlock();
+            serial::force_unlock();
+        }
+
+        error!("{}", title);
+
+        let graphics = display::is_graphics_mode();
+        let mut writer = Self {
+            graphics,
+            column: 0,
+            row: 0,
+        };
+
+        writer.set_color(Color256::RED, Color16::Red);
+        let _ = writeln!(writer, "{}", title);
+        writer.set_color(Color256::WHITE, Color16::White);
+
+        writer
+    }
+
+    fn set_color(&self, graphics_color: Color256, text_color: Color16) {
+        match self.graphics {
+            true => set_graphics_color(graphics_color),
+            false => set_text_color!(text_color, Color16::Black),
+        }
+    }
+
+    fn finish(self) -> ! {
+        if self.graphics {
+            flush_buffer();
+        }
+
+        loop {
+            hlt();
+        }
+    }
+}
+
+impl fmt::Write for CrashWriter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        serial_print!("{}", s);
+
+        if !self.graphics {
+            print!("{}", s);
+            return Ok(());
+        }
+
+        if self.row == 0 && self.column == 0 {
+            clear_buffer();
+        }
+
+        for c in s.bytes() {
+            if c == b'\n' || self.column == COLUMNS {
+                self.column = 0;
+                self.row += 1;
+            }
+            if c == b'\n' || self.row >= ROWS {
+                continue;
+            }
+
+            draw_text(iter::once(c), self.column * CHAR_WIDTH, self.row * CHAR_HEIGHT);
+            self.column += 1;
+        }
+
+        Ok(())
+    }
+}
+
+// Shows the crash screen for a CPU exception and halts forever
+pub fn exception(
+    name: &str,
+    stack_frame: &InterruptStackFrame,
+    error_code: Option<ErrorCode>,
+) -> ! {
+    // Letting the test framework report the failure instead
+    if cfg!(test) {
+        panic!("EXCEPTION: {} {:?}\n{:#?}", name, error_code, stack_frame);
+    }
+
+    let mut writer = CrashWriter::new("EXCEPTION");
+    let _ = write_exception(&mut writer, name, stack_frame, error_code);
+    let _ = writeln!(writer);
+    let _ = backtrace::print(&mut writer);
+    writer.finish()
+}
+
+pub fn stack_overflow(stack: &str, stack_frame: &InterruptStackFrame) -> ! {
+    if cfg!(test) {
+        panic!("STACK OVERFLOW on the {} stack\n{:#?}", stack, stack_frame);
+    }
+
+    let mut writer = CrashWriter::new("STACK OVERFLOW");
+    let _ = writeln!(writer, "The {} stack overflowed", stack);
+    let _ = writeln!(writer);
+    let _ = writeln!(writer, "Address {:#018x}", Cr2::read().as_u64());
+    let _ = writeln!(writer, "RSP     {:#018x}", stack_frame.stack_pointer.as_u64());
+    let _ = writeln!(writer, "RIP     {:#018x}", stack_frame.instruction_pointer.as_u64());
+    let _ = writeln!(writer);
+    let _ = backtrace::print(&mut writer);
+    writer.finish()
+}
+
+pub fn panic(info: &PanicInfo) -> ! {
+    let mut writer = CrashWriter::new("KERNEL PANIC");
+    let _ = writeln!(writer, "{}", info);
+    let _ = writeln!(writer);
+    let _ = backtrace::print(&mut writer);
+    writer.finish()
+}
+
+fn write_exception(
+    f: &mut impl Write,
+    name: &str,
+    stack_frame: &InterruptStackFrame,
+    error_code: Option<ErrorCode>,
+) -> fmt::Result {
+    writeln!(f, "{}", name)?;
+    writeln!(f)?;
+
+    match error_code {
+        None => {}
+        Some(ErrorCode::Raw(code)) => writeln!(f, "Error code {:#x}", code)?,
+        Some(ErrorCode::Selector(0)) => writeln!(f, "Error code 0")?,
+        Some(ErrorCode::Selector(code)) => {
+            writeln!(f, "Error code {:#x}", code)?;
+            writeln!(
+                f,
+                "  {} index {}{}",
+                selector_table(code),
+                selector_index(code),
+                if code & 1 != 0 { ", external" } else { "" }
+            )?;
+        }
+        Some(ErrorCode::PageFault(code)) => {
+            writeln!(f, "Error code {:#x}", code.bits())?;
+            writeln!(f, "  {:?}", code)?;
+            writeln!(f, "Address {:#018x}", Cr2::read().as_u64())?;
+        }
+    }
+
+    writeln!(f)?;
+    writeln!(f, "RIP    {:#018x}", stack_frame.instruction_pointer.as_u64())?;
+    writeln!(f, "RSP    {:#018x}", stack_frame.stack_pointer.as_u64())?;
+    writeln!(f, "RFLAGS {:#018x}", stack_frame.cpu_flags)?;
+    writeln!(f, "CS     {:#06x}", stack_frame.code_segment)?;
+    writeln!(f, "SS     {:#06x}", stack_frame.stack_segment)?;
+    writeln!(f, "CR3    {:#018x}", Cr3::read().0.start_address().as_u64())
+}
+
+fn selector_table(code: u64) -> &'static str {
+    match (code >> 1) & 0b11 {
+        0b00 => "GDT",
+        0b10 => "LDT",
+        _ => "IDT",
+    }
+}
+
+fn selector_index(code: u64) -> u64 {
+    (code >> 3) & 0x1FFF
+}
+
+#[test_case]
+fn test_selector_error_code() {
+    // External event while loading entry 5 of the IDT
+    let code = (5 << 3) | 0b011;
+    assert_eq!(selector_table(code), "IDT");
+    assert_eq!(selector_index(code), 5);
+
+    assert_eq!(selector_table(0x10), "GDT");
+    assert_eq!(selector_index(0x10), 2);
+}
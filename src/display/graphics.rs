@@ -1,3 +1,4 @@
+use alloc::vec::Vec;
 use bresenham::Bresenham;
 use lazy_static::lazy_static;
 
@@ -237,6 +238,21 @@ pub fn flush_buffer() {
     }
 }
 
+// Flushes with the sprite on top, but leaves the buffer as it was so it can be flushed again
+pub fn flush_buffer_with(sprite: &Sprite, x: usize, y: usize) {
+    let covered: Vec<(usize, usize, Color256)> = sprite
+        .to_absolute_points(x, y)
+        .map(|pixel| (pixel.pos.0, pixel.pos.1, get_pixel(pixel.pos.0, pixel.pos.1)))
+        .collect();
+
+    draw_sprite(sprite, x, y);
+    flush_buffer();
+
+    for (x, y, color) in covered {
+        set_pixel!(x, y, color);
+    }
+}
+
 pub fn _set_pixel_with_lock(x: usize, y: usize) {
     let color = *CURRENT_GRAPHICS_COLOR.lock();
     _set_pixel(x, y, color);
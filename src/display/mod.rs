@@ -75,6 +75,19 @@ pub fn _set_text_color(color: TextModeColor) {
     WRITER.lock().set_color(color);
 }
 
+pub fn is_graphics_mode() -> bool {
+    *CURRENT_MODE.lock() == Mode::Graphics
+}
+
+// Only for the crash screen, the locks could be held by the code that crashed
+pub unsafe fn force_unlock() {
+    CURRENT_MODE.force_unlock();
+    TEXT.force_unlock();
+    DRAWER.force_unlock();
+    CURRENT_GRAPHICS_COLOR.force_unlock();
+    WRITER.force_unlock();
+}
+
 fn ensure_text_mode() {
     let mut current_mode = CURRENT_MODE.lock();
     if *current_mode != Mode::Text {
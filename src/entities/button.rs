@@ -3,11 +3,13 @@ use alloc::string::String;
 use crate::{
     display::{
         color::Color256,
-        graphics::{contains_point, Rectangle},
+        get_current_graphics_color,
+        graphics::{contains_point, draw_shape, Rectangle},
+        set_graphics_color,
         sprite::Sprite,
     },
     entities::{is_mouse_click, sprite::SpriteEntity, text::Text},
-    events::add_event,
+    events::post_event,
     game::{Entity, Event, Shareable},
 };
 
@@ -15,6 +17,7 @@ pub struct Button<E: Entity> {
     rect: Rectangle,
     entity: E,
     on_click: Event,
+    hovered: bool,
 }
 
 impl<E: Entity> Button<E> {
@@ -23,6 +26,7 @@ impl<E: Entity> Button<E> {
             rect,
             entity,
             on_click,
+            hovered: false,
         }
     }
 
@@ -37,12 +41,17 @@ impl Button<Text> {
             rect,
             entity: Text::new(rect, text),
             on_click,
+            hovered: false,
         }
     }
 
     pub fn set_color(&mut self, color: Color256) {
         self.entity.set_color(color);
     }
+
+    pub fn set_text<S: Into<String>>(&mut self, text: S) {
+        self.entity.set_text(text);
+    }
 }
 
 impl Button<SpriteEntity> {
@@ -51,24 +60,39 @@ impl Button<SpriteEntity> {
             rect,
             entity: SpriteEntity::new(rect.x, rect.y, sprite),
             on_click,
+            hovered: false,
         }
     }
 }
 
 impl<E: Entity> Entity for Button<E> {
     fn handle_event(&mut self, event: &Event, shared: &Shareable) {
-        if !is_mouse_click(event) {
-            return;
-        }
-
         let point = (shared.mouse_x as usize, shared.mouse_y as usize);
-        if contains_point(&self.rect, point) {
-            add_event(self.on_click.clone());
+
+        match event {
+            Event::MouseMove => self.hovered = contains_point(&self.rect, point),
+            _ if is_mouse_click(event) && contains_point(&self.rect, point) => {
+                post_event(self.on_click.clone());
+            }
+            _ => {}
         }
     }
 
     fn draw(&self, shared: &Shareable) {
-        self.entity.draw(shared)
+        self.entity.draw(shared);
+
+        // Outlined a pixel outside when the cursor is over it
+        if self.hovered {
+            let color = get_current_graphics_color();
+            set_graphics_color(Color256::WHITE);
+            draw_shape(&Rectangle {
+                x: self.rect.x.saturating_sub(1),
+                y: self.rect.y.saturating_sub(1),
+                width: self.rect.width + 2,
+                height: self.rect.height + 2,
+            });
+            set_graphics_color(color);
+        }
     }
 
     fn to_delete(&self, _: &Shareable) -> bool {
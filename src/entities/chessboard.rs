@@ -1,9 +1,17 @@
+use arrayvec::ArrayVec;
+
 use crate::{
-    display::{graphics::draw_sprite, sprite::Sprite},
+    display::{
+        color::Color256,
+        graphics::{draw_line, draw_shape, draw_sprite, Rectangle},
+        set_graphics_color,
+        sprite::Sprite,
+    },
     entities::is_mouse_click,
-    events::add_event,
-    game::{Entity, Event, Shareable, State},
-    load_sprite,
+    events::post_event,
+    game::{Entity, Event, MouseButton, Shareable, State},
+    load_sprite, set_pixel,
+    settings::Theme,
 };
 use cozy_chess::{Board, Color, Piece, Square};
 
@@ -39,6 +47,38 @@ const PIECE_CAPTURE: Sprite = load_sprite!("../../sprites/PieceCapture.data", SQ
 
 const KING_BLUSH: Sprite = load_sprite!("../../sprites/KingBlush.data", SQUARE_SIZE);
 
+const MAX_ARROWS: usize = 16;
+const ARROW_COLOR: Color256 = Color256::new(255, 170, 0);
+const ARROW_HEAD_LENGTH: isize = 6;
+const ARROW_HEAD_WIDTH: isize = 4;
+
+// Light and dark squares, the classic theme is the sprite
+fn theme_colors(theme: Theme) -> Option<(Color256, Color256)> {
+    match theme {
+        Theme::Classic => None,
+        Theme::Green => Some((Color256::new(232, 232, 208), Color256::new(112, 148, 80))),
+        Theme::Blue => Some((Color256::new(224, 232, 240), Color256::new(80, 112, 160))),
+    }
+}
+
+// Same as the sprite, the top left square is light even when the board is flipped
+fn draw_squares(light: Color256, dark: Color256) {
+    for row in 0..8 {
+        for column in 0..8 {
+            let color = match (row + column) % 2 == 0 {
+                true => light,
+                false => dark,
+            };
+            let (x, y) = (BOARD_X + column * SQUARE_SIZE, BOARD_Y + row * SQUARE_SIZE);
+            for dy in 0..SQUARE_SIZE {
+                for dx in 0..SQUARE_SIZE {
+                    set_pixel!(x + dx, y + dy, color);
+                }
+            }
+        }
+    }
+}
+
 pub fn piece_sprite(piece: Piece, color: Color) -> &'static Sprite {
     match (color, piece) {
         (Color::White, Piece::Pawn) => &W_PAWN,
@@ -78,21 +118,57 @@ where
     });
 }
 
-fn handle_square_selection(prev: Square, curr: Square, board: &Board) {
+// Returns false if no move goes from the first square to the second
+fn handle_square_selection(prev: Square, curr: Square, board: &Board) -> bool {
+    let mut found = false;
     for_each_move(prev, board, |mv| {
         // If user has clicked on a possible square to move to, play it
-        match (curr == mv.to, mv.promotion.is_some()) {
+        found = curr == mv.to;
+        match (found, mv.promotion.is_some()) {
             (true, false) => {
-                add_event(Event::PlayMove(mv));
+                post_event(Event::PlayMove(mv));
                 true
             }
             (true, true) => {
-                add_event(Event::DisplayPromotion(prev, curr));
+                post_event(Event::DisplayPromotion(prev, curr));
                 true
             }
             _ => false,
         }
     });
+    found
+}
+
+fn square_center(square: Square, shared: &Shareable) -> (isize, isize) {
+    let square = if shared.should_flip() {
+        square.flip_rank()
+    } else {
+        square
+    };
+    let (x, y) = to_xy(BOARD_X, BOARD_Y, square as usize);
+    ((x + SQUARE_SIZE / 2) as isize, (y + SQUARE_SIZE / 2) as isize)
+}
+
+fn isqrt(n: isize) -> isize {
+    let mut root = 0;
+    while (root + 1) * (root + 1) <= n {
+        root += 1;
+    }
+    root
+}
+
+fn draw_arrow(from: (isize, isize), to: (isize, isize)) {
+    let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+    let length = isqrt(dx * dx + dy * dy).max(1);
+
+    // Going back along the arrow, then to each side of it
+    let back = (dx * ARROW_HEAD_LENGTH / length, dy * ARROW_HEAD_LENGTH / length);
+    let side = (-dy * ARROW_HEAD_WIDTH / length, dx * ARROW_HEAD_WIDTH / length);
+
+    let point = |(x, y): (isize, isize)| (x as usize, y as usize);
+    draw_line(point(from), point(to));
+    draw_line(point(to), point((to.0 - back.0 + side.0, to.1 - back.1 + side.1)));
+    draw_line(point(to), point((to.0 - back.0 - side.0, to.1 - back.1 - side.1)));
 }
 
 pub fn is_checkmate(board: &Board) -> bool {
@@ -106,12 +182,174 @@ pub fn is_checkmate(board: &Board) -> bool {
 
 pub struct ChessBoard {
     square_selected: Option<cozy_chess::Square>,
+    // Annotations drawn with right clicks, from the first square clicked to the second
+    arrows: ArrayVec<(Square, Square), MAX_ARROWS>,
+    arrow_start: Option<Square>,
+    // Square of the piece following the cursor
+    dragged: Option<Square>,
 }
 
 impl ChessBoard {
     pub fn new() -> Self {
         Self {
             square_selected: None,
+            arrows: ArrayVec::new(),
+            arrow_start: None,
+            dragged: None,
+        }
+    }
+
+    fn square_under_mouse(shared: &Shareable) -> Option<Square> {
+        Self::square_at(shared.mouse_x, shared.mouse_y, shared)
+    }
+
+    fn square_at(x: i16, y: i16, shared: &Shareable) -> Option<Square> {
+        let board_x = (x as usize).wrapping_sub(BOARD_X) / SQUARE_SIZE;
+        let board_y = (y as usize).wrapping_sub(BOARD_Y) / SQUARE_SIZE;
+
+        if board_x > 7 || board_y > 7 {
+            return None;
+        }
+
+        let square = Square::index(board_x + 8 * (7 - board_y));
+        match shared.should_flip() {
+            true => Some(square.flip_rank()),
+            false => Some(square),
+        }
+    }
+
+    fn handle_click(&mut self, shared: &Shareable) {
+        self.arrows.clear();
+        self.arrow_start = None;
+
+        // Earlier positions viewed with the wheel can't be played on
+        if shared.viewed.is_some() {
+            self.square_selected = None;
+            return;
+        }
+
+        let selected = match Self::square_under_mouse(shared) {
+            Some(square) => square,
+            None => {
+                self.square_selected = None;
+                return;
+            }
+        };
+
+        match self.square_selected {
+            // Clicking on same square
+            Some(sq) if sq == selected => self.square_selected = None,
+            Some(sq) => {
+                let played = handle_square_selection(sq, selected, &shared.board);
+
+                // Clicking on another piece selects it instead, so it can also be dragged
+                self.square_selected = match played {
+                    false if Self::is_movable(selected, shared) => Some(selected),
+                    _ => None,
+                };
+            }
+            None => self.square_selected = Some(selected),
+        }
+    }
+
+    fn is_movable(square: Square, shared: &Shareable) -> bool {
+        shared.board.color_on(square) == Some(shared.board.side_to_move())
+    }
+
+    // The press already went through `handle_click`, the piece gets selected again in case
+    // that press unselected it
+    fn handle_drag_start(&mut self, x: i16, y: i16, shared: &Shareable) {
+        if shared.viewed.is_some() {
+            return;
+        }
+
+        let square = match Self::square_at(x, y, shared) {
+            Some(square) if Self::is_movable(square, shared) => square,
+            _ => return,
+        };
+
+        self.square_selected = Some(square);
+        self.dragged = Some(square);
+    }
+
+    // Illegal drops leave the piece where it was
+    fn handle_drag_end(&mut self, shared: &Shareable) {
+        let from = match self.dragged.take() {
+            Some(from) => from,
+            None => return,
+        };
+
+        match Self::square_under_mouse(shared) {
+            // Dropped back on its square, it stays selected for a click on the destination
+            Some(to) if to == from => {}
+            Some(to) => {
+                handle_square_selection(from, to, &shared.board);
+                self.square_selected = None;
+            }
+            None => self.square_selected = None,
+        }
+    }
+
+    fn draw_dragged(&self, shared: &Shareable) {
+        let square = match self.dragged {
+            Some(square) => square,
+            None => return,
+        };
+
+        if let (Some(color), Some(piece)) =
+            (shared.board.color_on(square), shared.board.piece_on(square))
+        {
+            let x = (shared.mouse_x as usize).saturating_sub(SQUARE_SIZE / 2);
+            let y = (shared.mouse_y as usize).saturating_sub(SQUARE_SIZE / 2);
+            draw_sprite(piece_sprite(piece, color), x, y);
+        }
+    }
+
+    // Cancels the selection if there is one, otherwise draws an arrow between two squares
+    fn handle_right_click(&mut self, shared: &Shareable) {
+        if self.square_selected.take().is_some() {
+            return;
+        }
+
+        let square = match Self::square_under_mouse(shared) {
+            Some(square) => square,
+            None => {
+                self.arrow_start = None;
+                return;
+            }
+        };
+
+        match self.arrow_start.take() {
+            None => self.arrow_start = Some(square),
+            Some(start) if start == square => {}
+            Some(start) => match self.arrows.iter().position(|&a| a == (start, square)) {
+                // Drawing the same arrow again removes it
+                Some(i) => {
+                    self.arrows.remove(i);
+                }
+                None => {
+                    let _ = self.arrows.try_push((start, square));
+                }
+            },
+        }
+    }
+
+    fn draw_arrows(&self, shared: &Shareable) {
+        set_graphics_color(ARROW_COLOR);
+
+        for &(from, to) in &self.arrows {
+            draw_arrow(square_center(from, shared), square_center(to, shared));
+        }
+
+        if let Some(start) = self.arrow_start {
+            let (x, y) = square_center(start, shared);
+            let half = (SQUARE_SIZE / 2) as isize;
+            draw_shape(&Rectangle {
+                x: (x - half) as usize,
+                y: (y - half) as usize,
+                width: SQUARE_SIZE - 1,
+                height: SQUARE_SIZE - 1,
+            });
         }
     }
 
@@ -122,8 +360,13 @@ impl ChessBoard {
             } else {
                 *square
             };
-            let piece_sprite = match (shared.board.color_on(square), shared.board.piece_on(square))
-            {
+            // Drawn under the cursor instead
+            if self.dragged == Some(square) {
+                continue;
+            }
+
+            let board = shared.displayed_board();
+            let piece_sprite = match (board.color_on(square), board.piece_on(square)) {
                 (Some(color), Some(piece)) => piece_sprite(piece, color),
                 _ => continue,
             };
@@ -136,9 +379,10 @@ impl ChessBoard {
 
     fn draw_overlay_squares(&self, shared: &Shareable) {
         // King blush if in check :3
-        if shared.board.checkers().len() != 0 {
-            let stm = shared.board.side_to_move();
-            let mut sq = shared.board.king(stm);
+        let board = shared.displayed_board();
+        if board.checkers().len() != 0 {
+            let stm = board.side_to_move();
+            let mut sq = board.king(stm);
             if shared.should_flip() {
                 sq = sq.flip_rank();
             }
@@ -188,44 +432,29 @@ impl Entity for ChessBoard {
         }
 
         if is_checkmate(&shared.board) {
-            add_event(Event::EndGame);
-            return;
-        }
-
-        if !is_mouse_click(event) {
-            return;
-        }
-
-        let board_x = (shared.mouse_x as usize).wrapping_sub(BOARD_X) / SQUARE_SIZE;
-        let board_y = (shared.mouse_y as usize).wrapping_sub(BOARD_Y) / SQUARE_SIZE;
-
-        if board_x > 7 || board_y > 7 {
-            self.square_selected = None;
+            post_event(Event::EndGame);
             return;
         }
 
-        // Valid square has been selected in the board
-
-        let sq_index = board_x + 8 * (7 - board_y);
-        let mut selected = Square::index(sq_index);
-
-        if shared.should_flip() {
-            selected = selected.flip_rank();
-        }
-
-        match self.square_selected {
-            // Clicking on same square
-            Some(sq) if sq == selected => self.square_selected = None,
-            Some(sq) => {
-                handle_square_selection(sq, selected, &shared.board);
-                self.square_selected = None;
+        match event {
+            Event::MousePress(MouseButton::Right) => self.handle_right_click(shared),
+            Event::MousePress(MouseButton::Middle) => {
+                self.arrows.clear();
+                self.arrow_start = None;
             }
-            None => self.square_selected = Some(selected),
+            Event::PlayMove(_) => self.arrows.clear(),
+            Event::DragStart(x, y) => self.handle_drag_start(*x, *y, shared),
+            Event::DragEnd => self.handle_drag_end(shared),
+            _ if is_mouse_click(event) => self.handle_click(shared),
+            _ => {}
         }
     }
 
     fn draw(&self, shared: &Shareable) {
-        draw_sprite(&CHESSBOARD, BOARD_X, BOARD_Y);
+        match theme_colors(shared.theme) {
+            Some((light, dark)) => draw_squares(light, dark),
+            None => draw_sprite(&CHESSBOARD, BOARD_X, BOARD_Y),
+        }
         draw_sprite(
             &CHESSBOARD_BORDER,
             BOARD_X - BORDER_SIZE,
@@ -235,6 +464,10 @@ impl Entity for ChessBoard {
         self.draw_board(shared);
 
         self.draw_overlay_squares(shared);
+
+        self.draw_arrows(shared);
+
+        self.draw_dragged(shared);
     }
 
     fn to_delete(&self, _: &Shareable) -> bool {
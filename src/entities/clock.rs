@@ -0,0 +1,50 @@
+use core::time::Duration;
+
+use crate::{
+    display::graphics::{Rectangle, HEIGHT, WIDTH},
+    entities::text::Text,
+    game::{Entity, Event, Shareable},
+    rtc,
+    time::Deadline,
+};
+
+const CLOCK: Rectangle = Rectangle {
+    x: WIDTH - 72,
+    y: HEIGHT - 20,
+    width: 68,
+    height: 16,
+};
+
+pub struct Clock {
+    text: Text,
+    next_update: Deadline,
+}
+
+impl Clock {
+    pub fn new() -> Self {
+        Self {
+            text: Text::new(CLOCK, rtc::now().time_string()),
+            next_update: Deadline::after(Duration::from_secs(1)),
+        }
+    }
+}
+
+impl Entity for Clock {
+    fn handle_event(&mut self, _: &Event, _: &Shareable) {
+        // Reading the CMOS is slow, so only doing it once per second at most
+        if !self.next_update.has_passed() {
+            return;
+        }
+
+        self.text.set_text(rtc::now().time_string());
+        self.next_update = Deadline::after(Duration::from_secs(1));
+    }
+
+    fn draw(&self, shared: &Shareable) {
+        self.text.draw(shared);
+    }
+
+    fn to_delete(&self, _: &Shareable) -> bool {
+        false
+    }
+}
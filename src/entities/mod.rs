@@ -1,25 +1,21 @@
 
 
-use crate::{
-    game::{Event},
-};
-
-
-
+use crate::game::{Event, MouseButton};
 
 pub mod button;
 pub mod chessboard;
+pub mod clock;
 pub mod colorselector;
 pub mod difficultyselector;
 pub mod engineeval;
 pub mod enginethinking;
+pub mod moveinput;
+pub mod movelist;
 pub mod promotion;
+pub mod settingsselector;
 pub mod sprite;
 pub mod text;
 
 fn is_mouse_click(event: &Event) -> bool {
-    match event {
-        Event::MouseInput(input) => input.left_button_down(),
-        _ => false,
-    }
+    matches!(event, Event::MousePress(MouseButton::Left))
 }
@@ -0,0 +1,131 @@
+use alloc::{format, string::String};
+use pc_keyboard::DecodedKey;
+
+use crate::{
+    display::{color::Color256, graphics::Rectangle},
+    entities::{
+        chessboard::{BOARD_X, BOARD_Y, BORDER_SIZE, SQUARE_SIZE},
+        text::Text,
+    },
+    events::post_event,
+    game::{Entity, Event, Shareable, State},
+    notation::parse_move,
+};
+
+const MOVE_INPUT: Rectangle = Rectangle {
+    x: BOARD_X - BORDER_SIZE,
+    y: BOARD_Y + 8 * SQUARE_SIZE + BORDER_SIZE + 4,
+    width: 8 * SQUARE_SIZE + 2 * BORDER_SIZE,
+    height: 14,
+};
+
+const MAX_INPUT_LEN: usize = 8;
+
+const BACKSPACE: char = '\u{8}';
+const ESCAPE: char = '\u{1b}';
+
+const GRAY: Color256 = Color256::new(128, 128, 128);
+
+// Lets the player type moves in SAN or UCI instead of using the mouse
+pub struct MoveInput {
+    input: String,
+    text: Text,
+    error: bool,
+}
+
+impl MoveInput {
+    pub fn new() -> Self {
+        let mut s = Self {
+            input: String::new(),
+            text: Text::new(MOVE_INPUT, ""),
+            error: false,
+        };
+        s.update_text(None);
+        s
+    }
+
+    fn can_type(shared: &Shareable) -> bool {
+        shared.state == State::InGame
+            && !shared.in_promotion
+            && shared.board.side_to_move() == shared.user_color
+    }
+
+    fn update_text(&mut self, shared: Option<&Shareable>) {
+        if self.input.is_empty() {
+            self.text.set_text("Type a move");
+            self.text.set_color(GRAY);
+            return;
+        }
+
+        // Showing in green once the input is a legal move
+        let legal = shared
+            .map(|shared| parse_move(&shared.board, &self.input).is_ok())
+            .unwrap_or(false);
+
+        self.text.set_text(self.input.clone());
+        self.text.set_color(if legal {
+            Color256::GREEN
+        } else {
+            Color256::WHITE
+        });
+    }
+
+    fn submit(&mut self, shared: &Shareable) {
+        match parse_move(&shared.board, &self.input) {
+            Ok(mv) => {
+                self.input.clear();
+                self.update_text(Some(shared));
+                post_event(Event::PlayMove(mv));
+            }
+            Err(err) => {
+                self.text.set_text(format!("{}: {}", err.message(), self.input));
+                self.text.set_color(Color256::RED);
+                self.input.clear();
+                self.error = true;
+            }
+        }
+    }
+}
+
+impl Entity for MoveInput {
+    fn handle_event(&mut self, event: &Event, shared: &Shareable) {
+        let c = match event {
+            Event::KeyboardInput(DecodedKey::Unicode(c)) => *c,
+            _ => return,
+        };
+
+        if !Self::can_type(shared) {
+            return;
+        }
+
+        // The error stays until the next key
+        self.error = false;
+
+        match c {
+            '\n' | '\r' if !self.input.is_empty() => return self.submit(shared),
+            BACKSPACE => {
+                self.input.pop();
+            }
+            ESCAPE => self.input.clear(),
+            'a'..='h' | '1'..='8' | 'x' | '=' | '+' | '#' | '-' | '0' | 'O' | 'N' | 'B' | 'R'
+            | 'Q' | 'K' | 'n' | 'r' | 'q'
+                if self.input.len() < MAX_INPUT_LEN =>
+            {
+                self.input.push(c)
+            }
+            _ => {}
+        }
+
+        self.update_text(Some(shared));
+    }
+
+    fn draw(&self, shared: &Shareable) {
+        if Self::can_type(shared) || self.error {
+            self.text.draw(shared);
+        }
+    }
+
+    fn to_delete(&self, _: &Shareable) -> bool {
+        false
+    }
+}
@@ -0,0 +1,79 @@
+use alloc::{format, string::String, vec::Vec};
+
+use cozy_chess::{Board, Move};
+
+use crate::{
+    display::{
+        color::Color256,
+        graphics::{draw_text, CHAR_HEIGHT, CHAR_WIDTH},
+        set_graphics_color,
+    },
+    entities::chessboard::{BOARD_X, BOARD_Y, BORDER_SIZE, SQUARE_SIZE},
+    game::{Entity, Event, Shareable},
+    notation::to_san,
+};
+
+const MOVE_LIST_X: usize = 2;
+const MOVE_LIST_Y: usize = BOARD_Y;
+const MAX_CHARS: usize = (BOARD_X - BORDER_SIZE - MOVE_LIST_X) / CHAR_WIDTH;
+const LINES: usize = 8 * SQUARE_SIZE / CHAR_HEIGHT;
+
+const GRAY: Color256 = Color256::new(128, 128, 128);
+
+// The moves of the game in SAN, one per line, following the position viewed with the wheel
+pub struct MoveList {
+    moves: Vec<String>,
+}
+
+impl MoveList {
+    pub fn new() -> Self {
+        Self { moves: Vec::new() }
+    }
+
+    // For a continued game, the SAN of each move depends on the position it was played in
+    pub fn from_game(start: &Board, moves: &[Move]) -> Self {
+        let mut board = start.clone();
+        let moves = moves
+            .iter()
+            .map(|&mv| {
+                let san = to_san(&board, mv);
+                board.play_unchecked(mv);
+                san
+            })
+            .collect();
+        Self { moves }
+    }
+}
+
+impl Entity for MoveList {
+    fn handle_event(&mut self, event: &Event, shared: &Shareable) {
+        // Entities get the event before the game plays it, so this is still the position before
+        if let Event::PlayMove(mv) = event {
+            self.moves.push(to_san(&shared.board, *mv));
+        }
+    }
+
+    fn draw(&self, shared: &Shareable) {
+        let ply = shared.viewed.as_ref().map_or(self.moves.len(), |(ply, _)| *ply);
+        let first = ply.saturating_sub(LINES);
+
+        for (i, san) in self.moves.iter().enumerate().skip(first).take(LINES) {
+            // Moves after the viewed position are grayed out
+            set_graphics_color(match i < ply {
+                true => Color256::WHITE,
+                false => GRAY,
+            });
+
+            let line = match i % 2 {
+                0 => format!("{:>2}.{}", i / 2 + 1, san),
+                _ => format!("   {}", san),
+            };
+            let y = MOVE_LIST_Y + (i - first) * CHAR_HEIGHT;
+            draw_text(line.bytes().take(MAX_CHARS), MOVE_LIST_X, y);
+        }
+    }
+
+    fn to_delete(&self, _: &Shareable) -> bool {
+        false
+    }
+}
@@ -6,7 +6,7 @@ use crate::{
         chessboard::{piece_sprite, BOARD_X, BOARD_Y, SQUARE_SIZE},
         is_mouse_click,
     },
-    events::add_event,
+    events::post_event,
     game::{Entity, Event, Shareable},
     load_sprite,
 };
@@ -51,7 +51,7 @@ impl Entity for PromotionDisplayer {
 
         let piece = PromotionDisplayer::PIECES[promotion_x];
 
-        add_event(Event::PlayMove(Move {
+        post_event(Event::PlayMove(Move {
             from: self.from,
             to: self.to,
             promotion: Some(piece),
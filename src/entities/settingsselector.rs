@@ -0,0 +1,77 @@
+use alloc::{format, string::String};
+
+use crate::{
+    display::graphics::{Rectangle, WIDTH},
+    entities::{button::Button, text::Text},
+    game::{Entity, Event, Shareable},
+    settings::Theme,
+};
+
+pub struct SettingsSelector {
+    theme_button: Button<Text>,
+    sound_button: Button<Text>,
+    // What the buttons show, their text is only rebuilt when it changes
+    theme: Theme,
+    sound: bool,
+}
+
+fn theme_text(theme: Theme) -> String {
+    format!("Theme: {}", theme.name())
+}
+
+fn sound_text(sound: bool) -> &'static str {
+    match sound {
+        true => "Sound: On",
+        false => "Sound: Off",
+    }
+}
+
+impl SettingsSelector {
+    pub fn new(shared: &Shareable) -> Self {
+        const THEME: Rectangle = Rectangle {
+            x: WIDTH / 2 - 124,
+            y: 104,
+            width: 120,
+            height: 16,
+        };
+
+        const SOUND: Rectangle = Rectangle {
+            x: WIDTH / 2 + 4,
+            y: 104,
+            width: 120,
+            height: 16,
+        };
+
+        Self {
+            theme_button: Button::with_text(THEME, theme_text(shared.theme), Event::NextTheme),
+            sound_button: Button::with_text(SOUND, sound_text(shared.sound), Event::ToggleSound),
+            theme: shared.theme,
+            sound: shared.sound,
+        }
+    }
+}
+
+impl Entity for SettingsSelector {
+    fn handle_event(&mut self, event: &Event, shared: &Shareable) {
+        self.theme_button.handle_event(event, shared);
+        self.sound_button.handle_event(event, shared);
+
+        if self.theme != shared.theme {
+            self.theme = shared.theme;
+            self.theme_button.set_text(theme_text(self.theme));
+        }
+        if self.sound != shared.sound {
+            self.sound = shared.sound;
+            self.sound_button.set_text(sound_text(self.sound));
+        }
+    }
+
+    fn draw(&self, shared: &Shareable) {
+        self.theme_button.draw(shared);
+        self.sound_button.draw(shared);
+    }
+
+    fn to_delete(&self, _: &Shareable) -> bool {
+        false
+    }
+}
@@ -1,17 +1,26 @@
+use alloc::collections::VecDeque;
 use core::{
+    future::Future,
+    pin::Pin,
     sync::atomic::{AtomicI32, Ordering},
+    task::{Context, Poll},
 };
 
-
-
-
-
-
+use futures_util::task::AtomicWaker;
+use spin::Mutex;
+use x86_64::instructions::interrupts;
 
 use crate::{game::Event, queue::Queue};
 
 static mut EVENTS: SyncQueue<512> = SyncQueue::new();
 
+// Posted by the game and its entities while handling an event. Only the game empties the queue
+// above, so it can't wait there for room: this one grows instead and is emptied first
+static POSTED: Mutex<VecDeque<Event>> = Mutex::new(VecDeque::new());
+
+// Woken by every new event, including the ones coming from the keyboard and mouse interrupts
+static WAKER: AtomicWaker = AtomicWaker::new();
+
 #[derive(Debug)]
 struct Semaphore(AtomicI32);
 
@@ -42,18 +51,40 @@ impl Semaphore {
         }
     }
 
+    fn try_wait(&self) -> bool {
+        loop {
+            let val = self.0.load(Ordering::Relaxed);
+
+            if val <= 0 {
+                return false;
+            }
+
+            let res = self
+                .0
+                .compare_exchange(val, val - 1, Ordering::Relaxed, Ordering::Relaxed);
+
+            if res.is_ok() {
+                return true;
+            }
+        }
+    }
+
     fn signal(&self) {
         self.0.fetch_add(1, Ordering::Relaxed);
     }
 }
 
 impl<const N: usize> SyncQueue<N> {
-    fn add(&mut self, event: Event) {
-        self.empty.wait();
+    // Gives the event back when the queue is full
+    fn try_add(&mut self, event: Event) -> Result<(), Event> {
+        if !self.empty.try_wait() {
+            return Err(event);
+        }
         self.mutex.wait();
         self.elments.push_back(event);
         self.mutex.signal();
         self.full.signal();
+        Ok(())
     }
 
     fn poll(&mut self) -> Event {
@@ -68,6 +99,17 @@ impl<const N: usize> SyncQueue<N> {
         res
     }
 
+    fn try_poll(&mut self) -> Option<Event> {
+        if !self.full.try_wait() {
+            return None;
+        }
+        self.mutex.wait();
+        let res = self.elments.pop_front();
+        self.mutex.signal();
+        self.empty.signal();
+        res
+    }
+
     const fn new() -> Self {
         Self {
             elments: Queue::new(),
@@ -83,7 +125,59 @@ pub fn next_event() -> Event {
     unsafe { EVENTS.poll() }
 }
 
-// Blocks until event is added
-pub fn add_event(event: Event) {
-    unsafe { EVENTS.add(event) }
+// For the game and its entities, handled after the event being handled now
+pub fn post_event(event: Event) {
+    POSTED.lock().push_back(event);
+    WAKER.wake();
+}
+
+// For interrupt handlers and tasks, which must never wait for the game to empty the queue.
+// False if the queue is full and the event was dropped
+pub fn try_add_event(event: Event) -> bool {
+    // An interrupt adding an event while the lock is held here would never get it
+    let added = interrupts::without_interrupts(|| unsafe { EVENTS.try_add(event) }).is_ok();
+    if added {
+        WAKER.wake();
+    }
+    added
+}
+
+pub fn try_next_event() -> Option<Event> {
+    if let Some(event) = POSTED.lock().pop_front() {
+        return Some(event);
+    }
+    try_next_input()
+}
+
+// Only what the interrupts and tasks added, not what the game posted
+pub fn try_next_input() -> Option<Event> {
+    interrupts::without_interrupts(|| unsafe { EVENTS.try_poll() })
+}
+
+// Resolves once there is an event, without blocking the other tasks
+pub fn next() -> NextEvent {
+    NextEvent { _private: () }
+}
+
+pub struct NextEvent {
+    _private: (),
+}
+
+impl Future for NextEvent {
+    type Output = Event;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Event> {
+        if let Some(event) = try_next_event() {
+            return Poll::Ready(event);
+        }
+
+        WAKER.register(cx.waker());
+        match try_next_event() {
+            Some(event) => {
+                WAKER.take();
+                Poll::Ready(event)
+            }
+            None => Poll::Pending,
+        }
+    }
 }
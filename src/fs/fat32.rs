@@ -0,0 +1,1196 @@
+use alloc::{format, string::String, vec, vec::Vec};
+use core::ops::Range;
+
+use crate::{
+    fs::FsError,
+    rtc::{self, DateTime},
+    storage::{BlockDevice, BLOCK_SIZE},
+};
+
+// Microsoft's "FAT: General Overview of On-Disk Format" and https://wiki.osdev.org/FAT
+const BOOT_SIGNATURE: [u8; 2] = [0x55, 0xAA];
+const BOOT_SIGNATURE_OFFSET: usize = 510;
+
+// BIOS parameter block, in the first sector of the volume
+const BPB_JUMP: usize = 0;
+const BPB_OEM_NAME: usize = 3;
+const BPB_BYTES_PER_SECTOR: usize = 11;
+const BPB_SECTORS_PER_CLUSTER: usize = 13;
+const BPB_RESERVED_SECTORS: usize = 14;
+const BPB_FAT_COUNT: usize = 16;
+const BPB_ROOT_ENTRIES: usize = 17;
+const BPB_TOTAL_SECTORS_16: usize = 19;
+const BPB_MEDIA: usize = 21;
+const BPB_FAT_SIZE_16: usize = 22;
+const BPB_SECTORS_PER_TRACK: usize = 24;
+const BPB_HEADS: usize = 26;
+const BPB_TOTAL_SECTORS_32: usize = 32;
+const BPB_FAT_SIZE_32: usize = 36;
+const BPB_ROOT_CLUSTER: usize = 44;
+const BPB_FSINFO_SECTOR: usize = 48;
+const BPB_BACKUP_BOOT_SECTOR: usize = 50;
+const BPB_DRIVE_NUMBER: usize = 64;
+const BPB_EXTENDED_SIGNATURE: usize = 66;
+const BPB_VOLUME_ID: usize = 67;
+const BPB_VOLUME_LABEL: usize = 71;
+const BPB_FS_TYPE: usize = 82;
+
+// The volume can also be the first FAT32 partition of an MBR partition table
+const PARTITION_TABLE: usize = 446;
+const PARTITION_COUNT: usize = 4;
+const PARTITION_ENTRY_SIZE: usize = 16;
+const PARTITION_TYPE: usize = 4;
+const PARTITION_START: usize = 8;
+const PARTITION_TYPES_FAT32: [u8; 2] = [0x0B, 0x0C];
+
+const FSINFO_LEAD_SIGNATURE: u32 = 0x4161_5252;
+const FSINFO_STRUCT_SIGNATURE: u32 = 0x6141_7272;
+const FSINFO_TRAIL_SIGNATURE: u32 = 0xAA55_0000;
+const FSINFO_STRUCT: usize = 484;
+const FSINFO_FREE_COUNT: usize = 488;
+const FSINFO_NEXT_FREE: usize = 492;
+const FSINFO_TRAIL: usize = 508;
+const FSINFO_UNKNOWN: u32 = 0xFFFF_FFFF;
+
+// The top 4 bits of the entries are reserved
+const FAT_ENTRY_MASK: u32 = 0x0FFF_FFFF;
+const FAT_FREE: u32 = 0;
+const FAT_BAD_CLUSTER: u32 = 0x0FFF_FFF7;
+const FAT_END_OF_CHAIN: u32 = 0x0FFF_FFFF;
+const FIRST_CLUSTER: u32 = 2;
+const FAT_ENTRY_SIZE: usize = 4;
+
+// Directory entries
+const DIR_ENTRY_SIZE: usize = 32;
+const DIR_ATTRIBUTES: usize = 11;
+const DIR_CASE: usize = 12;
+const DIR_CREATION_TIME: usize = 14;
+const DIR_CREATION_DATE: usize = 16;
+const DIR_ACCESS_DATE: usize = 18;
+const DIR_CLUSTER_HIGH: usize = 20;
+const DIR_WRITE_TIME: usize = 22;
+const DIR_WRITE_DATE: usize = 24;
+const DIR_CLUSTER_LOW: usize = 26;
+const DIR_SIZE: usize = 28;
+
+const ENTRY_END: u8 = 0x00;
+const ENTRY_DELETED: u8 = 0xE5;
+
+const ATTR_VOLUME_ID: u8 = 0x08;
+const ATTR_DIRECTORY: u8 = 0x10;
+const ATTR_ARCHIVE: u8 = 0x20;
+const ATTR_LONG_NAME: u8 = 0x0F;
+const ATTR_LONG_NAME_MASK: u8 = 0x3F;
+
+// Set by Windows NT and mtools for short names that are all lowercase
+const CASE_LOWER_BASE: u8 = 0x08;
+const CASE_LOWER_EXTENSION: u8 = 0x10;
+
+const SHORT_NAME_LEN: usize = 11;
+const SHORT_BASE_LEN: usize = 8;
+const SHORT_EXTENSION_LEN: usize = 3;
+const SHORT_NAME_SPECIAL_CHARS: &[u8] = b"!#$%&'()-@^_`{}~";
+const MAX_NUMERIC_TAIL: u32 = 999_999;
+
+// Long file name entries, each holding 13 UTF-16 characters
+const LFN_LAST: u8 = 0x40;
+const LFN_ORDER_MASK: u8 = 0x1F;
+const LFN_CHECKSUM: usize = 13;
+const LFN_CHAR_OFFSETS: [usize; LFN_CHARS] = [1, 3, 5, 7, 9, 14, 16, 18, 20, 22, 24, 28, 30];
+const LFN_CHARS: usize = 13;
+const LFN_PADDING: u16 = 0xFFFF;
+const MAX_LFN_ENTRIES: usize = 20;
+const MAX_NAME_LEN: usize = 255;
+const INVALID_NAME_CHARS: &str = "\"*/:<>?\\|";
+
+// Used when formatting
+const FORMAT_RESERVED_SECTORS: u32 = 32;
+const FORMAT_FAT_COUNT: u32 = 2;
+const FORMAT_FSINFO_SECTOR: u16 = 1;
+const FORMAT_BACKUP_BOOT_SECTOR: u16 = 6;
+// With fewer clusters other systems see FAT12 or FAT16, whatever the boot sector says
+const FORMAT_MIN_CLUSTERS: u32 = 65525;
+const MEDIA_FIXED_DISK: u8 = 0xF8;
+const ZERO_SECTORS_PER_WRITE: usize = 64;
+
+// Dates are stored from 1980, with the seconds divided by 2
+const FAT_EPOCH_YEAR: u16 = 1980;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DirEntry {
+    pub name: String,
+    pub is_dir: bool,
+    pub size: u32,
+    pub modified: Option<DateTime>,
+    cluster: u32,
+    short_name: [u8; SHORT_NAME_LEN],
+    // Slots of the entry in its directory, from its first long name entry to the short one
+    first_slot: usize,
+    slot: usize,
+}
+
+// The clusters of a directory and their content
+struct Directory {
+    clusters: Vec<u32>,
+    data: Vec<u8>,
+}
+
+// The disk is only borrowed, mounting reads a couple of sectors so it is done for each operation
+pub struct Fat32<'a> {
+    disk: &'a mut dyn BlockDevice,
+    // First sector of the volume on the disk, the other sectors are relative to it
+    start: u64,
+    sectors_per_cluster: u64,
+    fat_start: u64,
+    fat_sectors: u64,
+    fat_count: u64,
+    data_start: u64,
+    cluster_count: u32,
+    root_cluster: u32,
+    fsinfo_sector: Option<u64>,
+    next_free: u32,
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([bytes[offset], bytes[offset + 1]])
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+fn write_u16(bytes: &mut [u8], offset: usize, value: u16) {
+    bytes[offset..offset + 2].copy_from_slice(&value.to_le_bytes());
+}
+
+fn write_u32(bytes: &mut [u8], offset: usize, value: u32) {
+    bytes[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+fn is_fat32_boot_sector(sector: &[u8]) -> bool {
+    let sectors_per_cluster = sector[BPB_SECTORS_PER_CLUSTER];
+
+    sector[BOOT_SIGNATURE_OFFSET..] == BOOT_SIGNATURE
+        && read_u16(sector, BPB_BYTES_PER_SECTOR) as usize == BLOCK_SIZE
+        && sectors_per_cluster.is_power_of_two()
+        && read_u16(sector, BPB_RESERVED_SECTORS) != 0
+        && sector[BPB_FAT_COUNT] != 0
+        // FAT12 and FAT16 have a fixed root directory and a 16 bit FAT size
+        && read_u16(sector, BPB_ROOT_ENTRIES) == 0
+        && read_u16(sector, BPB_FAT_SIZE_16) == 0
+        && read_u32(sector, BPB_FAT_SIZE_32) != 0
+}
+
+fn find_partition(mbr: &[u8]) -> Option<u64> {
+    (0..PARTITION_COUNT)
+        .map(|i| &mbr[PARTITION_TABLE + i * PARTITION_ENTRY_SIZE..][..PARTITION_ENTRY_SIZE])
+        .find(|entry| PARTITION_TYPES_FAT32.contains(&entry[PARTITION_TYPE]))
+        .map(|entry| read_u32(entry, PARTITION_START) as u64)
+}
+
+fn entry_cluster(raw: &[u8]) -> u32 {
+    (read_u16(raw, DIR_CLUSTER_HIGH) as u32) << 16 | read_u16(raw, DIR_CLUSTER_LOW) as u32
+}
+
+fn set_entry_cluster(raw: &mut [u8], cluster: u32) {
+    write_u16(raw, DIR_CLUSTER_HIGH, (cluster >> 16) as u16);
+    write_u16(raw, DIR_CLUSTER_LOW, cluster as u16);
+}
+
+fn fat_timestamp(now: DateTime) -> (u16, u16) {
+    let time = (now.hour as u16) << 11 | (now.minute as u16) << 5 | (now.second as u16 / 2);
+    let year = now.year.saturating_sub(FAT_EPOCH_YEAR);
+    let date = year << 9 | (now.month as u16) << 5 | now.day as u16;
+    (time, date)
+}
+
+fn parse_timestamp(time: u16, date: u16) -> Option<DateTime> {
+    if date == 0 {
+        return None;
+    }
+
+    Some(DateTime {
+        year: FAT_EPOCH_YEAR + (date >> 9),
+        month: (date >> 5 & 0x0F) as u8,
+        day: (date & 0x1F) as u8,
+        hour: (time >> 11) as u8,
+        minute: (time >> 5 & 0x3F) as u8,
+        second: (time & 0x1F) as u8 * 2,
+    })
+}
+
+fn set_write_time(raw: &mut [u8]) {
+    let (time, date) = fat_timestamp(rtc::now());
+    write_u16(raw, DIR_WRITE_TIME, time);
+    write_u16(raw, DIR_WRITE_DATE, date);
+    write_u16(raw, DIR_ACCESS_DATE, date);
+}
+
+fn short_name_checksum(short_name: &[u8; SHORT_NAME_LEN]) -> u8 {
+    short_name
+        .iter()
+        .fold(0u8, |sum, &c| sum.rotate_right(1).wrapping_add(c))
+}
+
+// "README  TXT" is shown as "README.TXT", or "readme.txt" with the lowercase flags
+fn short_name_to_string(short_name: &[u8; SHORT_NAME_LEN], case: u8) -> String {
+    let part = |bytes: &[u8], lower: bool| -> String {
+        let text = String::from_utf8_lossy(bytes);
+        let text = text.trim_end_matches(' ');
+        match lower {
+            true => text.to_ascii_lowercase(),
+            false => String::from(text),
+        }
+    };
+
+    let base = part(&short_name[..SHORT_BASE_LEN], case & CASE_LOWER_BASE != 0);
+    let extension = part(&short_name[SHORT_BASE_LEN..], case & CASE_LOWER_EXTENSION != 0);
+    match extension.is_empty() {
+        true => base,
+        false => format!("{}.{}", base, extension),
+    }
+}
+
+fn is_short_name_char(c: u8) -> bool {
+    c.is_ascii_uppercase() || c.is_ascii_digit() || SHORT_NAME_SPECIAL_CHARS.contains(&c)
+}
+
+fn validate_name(name: &str) -> Result<(), FsError> {
+    let valid = !name.is_empty()
+        && name.encode_utf16().count() <= MAX_NAME_LEN
+        && name != "."
+        && name != ".."
+        && !name.ends_with(['.', ' '])
+        && !name
+            .chars()
+            .any(|c| c.is_control() || INVALID_NAME_CHARS.contains(c));
+
+    match valid {
+        true => Ok(()),
+        false => Err(FsError::InvalidName),
+    }
+}
+
+// Names that fit in 8.3 with a single case for each part don't need long name entries
+fn exact_short_name(name: &str) -> Option<([u8; SHORT_NAME_LEN], u8)> {
+    let (base, extension) = name.split_once('.').unwrap_or((name, ""));
+    if base.is_empty()
+        || base.len() > SHORT_BASE_LEN
+        || extension.len() > SHORT_EXTENSION_LEN
+        || extension.contains('.')
+    {
+        return None;
+    }
+
+    let mut short_name = [b' '; SHORT_NAME_LEN];
+    let mut case = 0;
+    let parts = [
+        (base, 0, CASE_LOWER_BASE),
+        (extension, SHORT_BASE_LEN, CASE_LOWER_EXTENSION),
+    ];
+    for (part, offset, lower_flag) in parts {
+        let has_lower = part.bytes().any(|c| c.is_ascii_lowercase());
+        let has_upper = part.bytes().any(|c| c.is_ascii_uppercase());
+        if has_lower && has_upper {
+            return None;
+        }
+        if has_lower {
+            case |= lower_flag;
+        }
+
+        for (i, c) in part.bytes().enumerate() {
+            let c = c.to_ascii_uppercase();
+            if !is_short_name_char(c) {
+                return None;
+            }
+            short_name[offset + i] = c;
+        }
+    }
+
+    Some((short_name, case))
+}
+
+// Short name for a long name, "A long game.pgn" becomes "ALONGG~1.PGN"
+fn generated_short_name(
+    name: &str,
+    entries: &[DirEntry],
+) -> Result<[u8; SHORT_NAME_LEN], FsError> {
+    let (base, extension) = match name.rfind('.') {
+        Some(i) if i > 0 => (&name[..i], &name[i + 1..]),
+        _ => (name, ""),
+    };
+    let clean = |part: &str| -> Vec<u8> {
+        part.chars()
+            .filter(|&c| c != ' ' && c != '.')
+            .map(|c| match c.is_ascii() && is_short_name_char(c.to_ascii_uppercase() as u8) {
+                true => c.to_ascii_uppercase() as u8,
+                false => b'_',
+            })
+            .collect()
+    };
+    let base = clean(base);
+    let extension = clean(extension);
+
+    for n in 1..=MAX_NUMERIC_TAIL {
+        let tail = format!("~{}", n);
+        let kept = base.len().min(SHORT_BASE_LEN - tail.len());
+        let extension_len = extension.len().min(SHORT_EXTENSION_LEN);
+
+        let mut short_name = [b' '; SHORT_NAME_LEN];
+        short_name[..kept].copy_from_slice(&base[..kept]);
+        short_name[kept..kept + tail.len()].copy_from_slice(tail.as_bytes());
+        short_name[SHORT_BASE_LEN..SHORT_BASE_LEN + extension_len]
+            .copy_from_slice(&extension[..extension_len]);
+
+        if !entries.iter().any(|entry| entry.short_name == short_name) {
+            return Ok(short_name);
+        }
+    }
+
+    Err(FsError::DiskFull)
+}
+
+// Long name entries come before the short entry they belong to, in reverse order
+struct LongName {
+    units: [u16; MAX_LFN_ENTRIES * LFN_CHARS],
+    checksum: u8,
+    first_slot: usize,
+    next_order: u8,
+    valid: bool,
+}
+
+impl LongName {
+    fn new() -> Self {
+        Self {
+            units: [LFN_PADDING; MAX_LFN_ENTRIES * LFN_CHARS],
+            checksum: 0,
+            first_slot: 0,
+            next_order: 0,
+            valid: false,
+        }
+    }
+
+    fn add(&mut self, slot: usize, raw: &[u8]) {
+        let order = raw[0] & LFN_ORDER_MASK;
+
+        if raw[0] & LFN_LAST != 0 {
+            *self = Self::new();
+            self.checksum = raw[LFN_CHECKSUM];
+            self.first_slot = slot;
+            self.next_order = order;
+            self.valid = order != 0 && order as usize <= MAX_LFN_ENTRIES;
+        }
+
+        if !self.valid || order != self.next_order || raw[LFN_CHECKSUM] != self.checksum {
+            self.valid = false;
+            return;
+        }
+
+        let start = (order as usize - 1) * LFN_CHARS;
+        for (i, &offset) in LFN_CHAR_OFFSETS.iter().enumerate() {
+            self.units[start + i] = read_u16(raw, offset);
+        }
+        self.next_order -= 1;
+    }
+
+    // The name and its first slot, if the long name is complete and matches the short entry
+    fn take(&mut self, short_name: &[u8; SHORT_NAME_LEN]) -> Option<(String, usize)> {
+        let complete = self.valid
+            && self.next_order == 0
+            && self.checksum == short_name_checksum(short_name);
+        self.valid = false;
+        if !complete {
+            return None;
+        }
+
+        let len = self
+            .units
+            .iter()
+            .position(|&unit| unit == 0 || unit == LFN_PADDING)
+            .unwrap_or(self.units.len());
+        let name = char::decode_utf16(self.units[..len].iter().copied())
+            .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+            .collect();
+        Some((name, self.first_slot))
+    }
+}
+
+// Entries of a directory, without "." and ".." or the volume label
+fn parse_entries(data: &[u8]) -> Vec<DirEntry> {
+    let mut entries = Vec::new();
+    let mut long_name = LongName::new();
+
+    for (slot, raw) in data.chunks_exact(DIR_ENTRY_SIZE).enumerate() {
+        match raw[0] {
+            ENTRY_END => break,
+            ENTRY_DELETED => {
+                long_name.valid = false;
+                continue;
+            }
+            _ => {}
+        }
+
+        let attributes = raw[DIR_ATTRIBUTES];
+        if attributes & ATTR_LONG_NAME_MASK == ATTR_LONG_NAME {
+            long_name.add(slot, raw);
+            continue;
+        }
+        if attributes & ATTR_VOLUME_ID != 0 || raw[0] == b'.' {
+            long_name.valid = false;
+            continue;
+        }
+
+        let short_name: [u8; SHORT_NAME_LEN] = raw[..SHORT_NAME_LEN].try_into().unwrap();
+        let (name, first_slot) = long_name
+            .take(&short_name)
+            .unwrap_or_else(|| (short_name_to_string(&short_name, raw[DIR_CASE]), slot));
+
+        entries.push(DirEntry {
+            name,
+            is_dir: attributes & ATTR_DIRECTORY != 0,
+            size: read_u32(raw, DIR_SIZE),
+            modified: parse_timestamp(read_u16(raw, DIR_WRITE_TIME), read_u16(raw, DIR_WRITE_DATE)),
+            cluster: entry_cluster(raw),
+            short_name,
+            first_slot,
+            slot,
+        });
+    }
+
+    entries
+}
+
+fn find_entry(entries: Vec<DirEntry>, name: &str) -> Option<DirEntry> {
+    entries
+        .into_iter()
+        .find(|entry| entry.name.eq_ignore_ascii_case(name))
+}
+
+// Finds `count` free slots in a row
+fn find_free_slots(data: &[u8], count: usize) -> Option<usize> {
+    let mut run = 0;
+    for (slot, raw) in data.chunks_exact(DIR_ENTRY_SIZE).enumerate() {
+        match raw[0] {
+            ENTRY_END | ENTRY_DELETED => run += 1,
+            _ => run = 0,
+        }
+        if run == count {
+            return Some(slot + 1 - count);
+        }
+    }
+    None
+}
+
+fn components(path: &str) -> impl Iterator<Item = &str> {
+    path.split('/').filter(|component| !component.is_empty())
+}
+
+// Splits "/games/last.pgn" into "/games" and "last.pgn"
+fn split_path(path: &str) -> Result<(&str, &str), FsError> {
+    let path = path.trim_end_matches('/');
+    let (parent, name) = path.rsplit_once('/').unwrap_or(("", path));
+    validate_name(name)?;
+    Ok((parent, name))
+}
+
+// Microsoft's recommended cluster sizes for FAT32
+fn sectors_per_cluster_for(total_sectors: u32) -> u32 {
+    match total_sectors {
+        0..=532_480 => 1,
+        532_481..=16_777_216 => 8,
+        16_777_217..=33_554_432 => 16,
+        33_554_433..=67_108_864 => 32,
+        _ => 64,
+    }
+}
+
+impl<'a> Fat32<'a> {
+    pub fn mount(disk: &'a mut dyn BlockDevice) -> Result<Self, FsError> {
+        let mut sector = [0u8; BLOCK_SIZE];
+        disk.read_blocks(0, &mut sector)?;
+
+        let start = match is_fat32_boot_sector(&sector) {
+            true => 0,
+            false => {
+                let start = find_partition(&sector)
+                    .filter(|_| sector[BOOT_SIGNATURE_OFFSET..] == BOOT_SIGNATURE)
+                    .ok_or(FsError::NotFat32)?;
+                disk.read_blocks(start, &mut sector)?;
+                if !is_fat32_boot_sector(&sector) {
+                    return Err(FsError::NotFat32);
+                }
+                start
+            }
+        };
+
+        let sectors_per_cluster = sector[BPB_SECTORS_PER_CLUSTER] as u64;
+        let fat_start = read_u16(&sector, BPB_RESERVED_SECTORS) as u64;
+        let fat_count = sector[BPB_FAT_COUNT] as u64;
+        let fat_sectors = read_u32(&sector, BPB_FAT_SIZE_32) as u64;
+        let total_sectors = match read_u16(&sector, BPB_TOTAL_SECTORS_16) {
+            0 => read_u32(&sector, BPB_TOTAL_SECTORS_32) as u64,
+            total => total as u64,
+        };
+        let data_start = fat_start + fat_count * fat_sectors;
+        if total_sectors <= data_start || start + total_sectors > disk.block_count() {
+            return Err(FsError::Corrupted);
+        }
+
+        // Also limited by the number of entries that fit in the FAT
+        let max_clusters = fat_sectors * (BLOCK_SIZE / FAT_ENTRY_SIZE) as u64 - 2;
+        let cluster_count = ((total_sectors - data_start) / sectors_per_cluster).min(max_clusters);
+
+        let fsinfo_sector = match read_u16(&sector, BPB_FSINFO_SECTOR) as u64 {
+            0 | 0xFFFF => None,
+            fsinfo => Some(fsinfo),
+        };
+
+        let mut fs = Self {
+            disk,
+            start,
+            sectors_per_cluster,
+            fat_start,
+            fat_sectors,
+            fat_count,
+            data_start,
+            cluster_count: cluster_count as u32,
+            root_cluster: read_u32(&sector, BPB_ROOT_CLUSTER),
+            fsinfo_sector,
+            next_free: FIRST_CLUSTER,
+        };
+        fs.read_fsinfo()?;
+        Ok(fs)
+    }
+
+    // Formats the whole disk as a single FAT32 volume, without a partition table
+    pub fn format(disk: &mut dyn BlockDevice) -> Result<(), FsError> {
+        let total_sectors = disk.block_count().min(u32::MAX as u64) as u32;
+        let sectors_per_cluster = sectors_per_cluster_for(total_sectors);
+
+        // The FAT size depends on the number of clusters, which depends on the FAT size
+        let mut fat_sectors = 1;
+        let clusters = loop {
+            let used = FORMAT_RESERVED_SECTORS + FORMAT_FAT_COUNT * fat_sectors;
+            let clusters = total_sectors.saturating_sub(used) / sectors_per_cluster;
+            let needed = ((clusters + 2) as usize * FAT_ENTRY_SIZE + BLOCK_SIZE - 1) / BLOCK_SIZE;
+            if needed as u32 <= fat_sectors {
+                break clusters;
+            }
+            fat_sectors = needed as u32;
+        };
+        if clusters < FORMAT_MIN_CLUSTERS {
+            return Err(FsError::TooSmall);
+        }
+
+        let root_cluster = FIRST_CLUSTER;
+        let (time, date) = fat_timestamp(rtc::now());
+
+        let mut boot = [0u8; BLOCK_SIZE];
+        boot[BPB_JUMP..BPB_JUMP + 3].copy_from_slice(&[0xEB, 0x58, 0x90]);
+        boot[BPB_OEM_NAME..BPB_OEM_NAME + 8].copy_from_slice(b"BMC-OS  ");
+        write_u16(&mut boot, BPB_BYTES_PER_SECTOR, BLOCK_SIZE as u16);
+        boot[BPB_SECTORS_PER_CLUSTER] = sectors_per_cluster as u8;
+        write_u16(&mut boot, BPB_RESERVED_SECTORS, FORMAT_RESERVED_SECTORS as u16);
+        boot[BPB_FAT_COUNT] = FORMAT_FAT_COUNT as u8;
+        boot[BPB_MEDIA] = MEDIA_FIXED_DISK;
+        write_u16(&mut boot, BPB_SECTORS_PER_TRACK, 63);
+        write_u16(&mut boot, BPB_HEADS, 255);
+        write_u32(&mut boot, BPB_TOTAL_SECTORS_32, total_sectors);
+        write_u32(&mut boot, BPB_FAT_SIZE_32, fat_sectors);
+        write_u32(&mut boot, BPB_ROOT_CLUSTER, root_cluster);
+        write_u16(&mut boot, BPB_FSINFO_SECTOR, FORMAT_FSINFO_SECTOR);
+        write_u16(&mut boot, BPB_BACKUP_BOOT_SECTOR, FORMAT_BACKUP_BOOT_SECTOR);
+        boot[BPB_DRIVE_NUMBER] = 0x80;
+        boot[BPB_EXTENDED_SIGNATURE] = 0x29;
+        write_u32(&mut boot, BPB_VOLUME_ID, (date as u32) << 16 | time as u32);
+        boot[BPB_VOLUME_LABEL..BPB_VOLUME_LABEL + 11].copy_from_slice(b"BMC-OS     ");
+        boot[BPB_FS_TYPE..BPB_FS_TYPE + 8].copy_from_slice(b"FAT32   ");
+        boot[BOOT_SIGNATURE_OFFSET..].copy_from_slice(&BOOT_SIGNATURE);
+
+        let mut fsinfo = [0u8; BLOCK_SIZE];
+        write_u32(&mut fsinfo, 0, FSINFO_LEAD_SIGNATURE);
+        write_u32(&mut fsinfo, FSINFO_STRUCT, FSINFO_STRUCT_SIGNATURE);
+        write_u32(&mut fsinfo, FSINFO_FREE_COUNT, clusters - 1);
+        write_u32(&mut fsinfo, FSINFO_NEXT_FREE, root_cluster + 1);
+        write_u32(&mut fsinfo, FSINFO_TRAIL, FSINFO_TRAIL_SIGNATURE);
+
+        for copy in [0, FORMAT_BACKUP_BOOT_SECTOR as u64] {
+            disk.write_blocks(copy, &boot)?;
+            disk.write_blocks(copy + FORMAT_FSINFO_SECTOR as u64, &fsinfo)?;
+        }
+
+        // Clearing both FATs and the root directory, which is the first cluster
+        let zero_start = FORMAT_RESERVED_SECTORS as u64;
+        let zero_end = zero_start
+            + (FORMAT_FAT_COUNT * fat_sectors) as u64
+            + sectors_per_cluster as u64;
+        let zeros = vec![0u8; ZERO_SECTORS_PER_WRITE * BLOCK_SIZE];
+        let mut lba = zero_start;
+        while lba < zero_end {
+            let count = (zero_end - lba).min(ZERO_SECTORS_PER_WRITE as u64);
+            disk.write_blocks(lba, &zeros[..count as usize * BLOCK_SIZE])?;
+            lba += count;
+        }
+
+        // The first two entries are reserved, the third is the root directory
+        let mut fat = [0u8; BLOCK_SIZE];
+        write_u32(&mut fat, 0, FAT_END_OF_CHAIN & !0xFF | MEDIA_FIXED_DISK as u32);
+        write_u32(&mut fat, FAT_ENTRY_SIZE, FAT_END_OF_CHAIN);
+        write_u32(&mut fat, root_cluster as usize * FAT_ENTRY_SIZE, FAT_END_OF_CHAIN);
+        for copy in 0..FORMAT_FAT_COUNT {
+            disk.write_blocks((FORMAT_RESERVED_SECTORS + copy * fat_sectors) as u64, &fat)?;
+        }
+
+        disk.flush()?;
+        Ok(())
+    }
+
+    pub fn list_dir(&mut self, path: &str) -> Result<Vec<DirEntry>, FsError> {
+        let cluster = self.resolve_dir(path)?;
+        let dir = self.read_dir(cluster)?;
+        Ok(parse_entries(&dir.data))
+    }
+
+    pub fn metadata(&mut self, path: &str) -> Result<DirEntry, FsError> {
+        let mut entry = self.root_entry();
+        for component in components(path) {
+            if !entry.is_dir {
+                return Err(FsError::NotADirectory);
+            }
+            let dir = self.read_dir(self.dir_cluster(entry.cluster))?;
+            entry = find_entry(parse_entries(&dir.data), component).ok_or(FsError::NotFound)?;
+        }
+        Ok(entry)
+    }
+
+    pub fn read_file(&mut self, path: &str) -> Result<Vec<u8>, FsError> {
+        let entry = self.metadata(path)?;
+        if entry.is_dir {
+            return Err(FsError::IsADirectory);
+        }
+
+        let clusters = self.chain(entry.cluster)?;
+        let cluster_size = self.cluster_size();
+        if clusters.len() * cluster_size < entry.size as usize {
+            return Err(FsError::Corrupted);
+        }
+
+        let mut data = vec![0u8; clusters.len() * cluster_size];
+        for (&cluster, chunk) in clusters.iter().zip(data.chunks_mut(cluster_size)) {
+            self.read_cluster(cluster, chunk)?;
+        }
+        data.truncate(entry.size as usize);
+        Ok(data)
+    }
+
+    // Creates the file or replaces its content
+    pub fn write_file(&mut self, path: &str, data: &[u8]) -> Result<(), FsError> {
+        let size = u32::try_from(data.len()).map_err(|_| FsError::FileTooLarge)?;
+        let (parent, name) = split_path(path)?;
+        let dir_cluster = self.resolve_dir(parent)?;
+        let mut dir = self.read_dir(dir_cluster)?;
+
+        let existing = find_entry(parse_entries(&dir.data), name);
+        if existing.as_ref().map_or(false, |entry| entry.is_dir) {
+            return Err(FsError::IsADirectory);
+        }
+
+        // The new content is written before the old one is freed, so a failure keeps the old file
+        let cluster_size = self.cluster_size();
+        let clusters = self.allocate_chain((data.len() + cluster_size - 1) / cluster_size)?;
+        let first = clusters.first().copied().unwrap_or(0);
+        if let Err(err) = self.write_clusters(&clusters, data) {
+            self.free_chain(first)?;
+            return Err(err);
+        }
+
+        let res = match &existing {
+            Some(entry) => {
+                let raw = &mut dir.data[entry.slot * DIR_ENTRY_SIZE..][..DIR_ENTRY_SIZE];
+                set_entry_cluster(raw, first);
+                write_u32(raw, DIR_SIZE, size);
+                set_write_time(raw);
+                self.write_dir_slots(&dir, entry.slot..entry.slot + 1)
+            }
+            None => self.add_entry(&mut dir, name, ATTR_ARCHIVE, first, size),
+        };
+
+        match (res, existing) {
+            (Err(err), _) => {
+                self.free_chain(first)?;
+                return Err(err);
+            }
+            (Ok(()), Some(entry)) => self.free_chain(entry.cluster)?,
+            (Ok(()), None) => {}
+        }
+
+        self.sync()
+    }
+
+    pub fn create_dir(&mut self, path: &str) -> Result<(), FsError> {
+        let (parent, name) = split_path(path)?;
+        let parent_cluster = self.resolve_dir(parent)?;
+        let mut dir = self.read_dir(parent_cluster)?;
+
+        if find_entry(parse_entries(&dir.data), name).is_some() {
+            return Err(FsError::AlreadyExists);
+        }
+
+        let cluster = self.allocate_cluster()?;
+
+        // ".." points to cluster 0 when the parent is the root directory
+        let dot_dot_cluster = match parent_cluster == self.root_cluster {
+            true => 0,
+            false => parent_cluster,
+        };
+        let dot_entries: [(&[u8; SHORT_NAME_LEN], u32); 2] =
+            [(b".          ", cluster), (b"..         ", dot_dot_cluster)];
+        let mut data = vec![0u8; self.cluster_size()];
+        for (slot, (short_name, target)) in dot_entries.into_iter().enumerate() {
+            let raw = &mut data[slot * DIR_ENTRY_SIZE..][..DIR_ENTRY_SIZE];
+            raw[..SHORT_NAME_LEN].copy_from_slice(short_name);
+            raw[DIR_ATTRIBUTES] = ATTR_DIRECTORY;
+            set_entry_cluster(raw, target);
+            set_write_time(raw);
+        }
+
+        let res = self
+            .write_cluster(cluster, &data)
+            .and_then(|()| self.add_entry(&mut dir, name, ATTR_DIRECTORY, cluster, 0));
+        if let Err(err) = res {
+            self.free_chain(cluster)?;
+            return Err(err);
+        }
+
+        self.sync()
+    }
+
+    // Removes a file or an empty directory
+    pub fn remove(&mut self, path: &str) -> Result<(), FsError> {
+        let (parent, name) = split_path(path)?;
+        let mut dir = self.read_dir(self.resolve_dir(parent)?)?;
+        let entry = find_entry(parse_entries(&dir.data), name).ok_or(FsError::NotFound)?;
+
+        if entry.is_dir {
+            let content = self.read_dir(entry.cluster)?;
+            if !parse_entries(&content.data).is_empty() {
+                return Err(FsError::NotEmpty);
+            }
+        }
+
+        for slot in entry.first_slot..=entry.slot {
+            dir.data[slot * DIR_ENTRY_SIZE] = ENTRY_DELETED;
+        }
+        self.write_dir_slots(&dir, entry.first_slot..entry.slot + 1)?;
+        self.free_chain(entry.cluster)?;
+
+        self.sync()
+    }
+
+    fn root_entry(&self) -> DirEntry {
+        DirEntry {
+            name: String::from("/"),
+            is_dir: true,
+            size: 0,
+            modified: None,
+            cluster: self.root_cluster,
+            short_name: [b' '; SHORT_NAME_LEN],
+            first_slot: 0,
+            slot: 0,
+        }
+    }
+
+    fn dir_cluster(&self, cluster: u32) -> u32 {
+        match cluster {
+            0 => self.root_cluster,
+            cluster => cluster,
+        }
+    }
+
+    fn resolve_dir(&mut self, path: &str) -> Result<u32, FsError> {
+        let entry = self.metadata(path)?;
+        match entry.is_dir {
+            true => Ok(self.dir_cluster(entry.cluster)),
+            false => Err(FsError::NotADirectory),
+        }
+    }
+
+    fn cluster_size(&self) -> usize {
+        self.sectors_per_cluster as usize * BLOCK_SIZE
+    }
+
+    fn read_sectors(&mut self, lba: u64, buf: &mut [u8]) -> Result<(), FsError> {
+        Ok(self.disk.read_blocks(self.start + lba, buf)?)
+    }
+
+    fn write_sectors(&mut self, lba: u64, buf: &[u8]) -> Result<(), FsError> {
+        Ok(self.disk.write_blocks(self.start + lba, buf)?)
+    }
+
+    fn cluster_lba(&self, cluster: u32) -> u64 {
+        self.data_start + (cluster - FIRST_CLUSTER) as u64 * self.sectors_per_cluster
+    }
+
+    fn read_cluster(&mut self, cluster: u32, buf: &mut [u8]) -> Result<(), FsError> {
+        self.read_sectors(self.cluster_lba(cluster), buf)
+    }
+
+    fn write_cluster(&mut self, cluster: u32, buf: &[u8]) -> Result<(), FsError> {
+        self.write_sectors(self.cluster_lba(cluster), buf)
+    }
+
+    fn is_valid_cluster(&self, cluster: u32) -> bool {
+        (FIRST_CLUSTER..FIRST_CLUSTER + self.cluster_count).contains(&cluster)
+    }
+
+    // Sector of the FAT holding the entry of the cluster, and the offset in it
+    fn fat_position(&self, cluster: u32) -> (u64, usize) {
+        let offset = cluster as usize * FAT_ENTRY_SIZE;
+        ((offset / BLOCK_SIZE) as u64, offset % BLOCK_SIZE)
+    }
+
+    fn fat_entry(&mut self, cluster: u32) -> Result<u32, FsError> {
+        let (sector, offset) = self.fat_position(cluster);
+        let mut buf = [0u8; BLOCK_SIZE];
+        self.read_sectors(self.fat_start + sector, &mut buf)?;
+        Ok(read_u32(&buf, offset) & FAT_ENTRY_MASK)
+    }
+
+    // Every copy of the FAT is kept the same
+    fn set_fat_entry(&mut self, cluster: u32, value: u32) -> Result<(), FsError> {
+        let (sector, offset) = self.fat_position(cluster);
+        let mut buf = [0u8; BLOCK_SIZE];
+        for copy in 0..self.fat_count {
+            let lba = self.fat_start + copy * self.fat_sectors + sector;
+            self.read_sectors(lba, &mut buf)?;
+            let reserved = read_u32(&buf, offset) & !FAT_ENTRY_MASK;
+            write_u32(&mut buf, offset, reserved | value);
+            self.write_sectors(lba, &buf)?;
+        }
+        Ok(())
+    }
+
+    fn chain(&mut self, first: u32) -> Result<Vec<u32>, FsError> {
+        let mut clusters = Vec::new();
+        let mut cluster = first;
+
+        // Empty files have no cluster
+        while cluster != 0 {
+            if !self.is_valid_cluster(cluster) || clusters.len() >= self.cluster_count as usize {
+                return Err(FsError::Corrupted);
+            }
+            clusters.push(cluster);
+
+            cluster = match self.fat_entry(cluster)? {
+                next if next > FAT_BAD_CLUSTER => 0,
+                next => next,
+            };
+        }
+
+        Ok(clusters)
+    }
+
+    fn allocate_cluster(&mut self) -> Result<u32, FsError> {
+        let start = match self.is_valid_cluster(self.next_free) {
+            true => self.next_free,
+            false => FIRST_CLUSTER,
+        };
+
+        // Scanning a sector of the FAT at a time from the hint
+        let mut buf = [0u8; BLOCK_SIZE];
+        let mut loaded = None;
+        for i in 0..self.cluster_count {
+            let cluster = FIRST_CLUSTER + (start - FIRST_CLUSTER + i) % self.cluster_count;
+            let (sector, offset) = self.fat_position(cluster);
+            if loaded != Some(sector) {
+                self.read_sectors(self.fat_start + sector, &mut buf)?;
+                loaded = Some(sector);
+            }
+
+            if read_u32(&buf, offset) & FAT_ENTRY_MASK == FAT_FREE {
+                self.set_fat_entry(cluster, FAT_END_OF_CHAIN)?;
+                self.next_free = cluster + 1;
+                return Ok(cluster);
+            }
+        }
+
+        Err(FsError::DiskFull)
+    }
+
+    // Allocates the clusters and links them, nothing stays allocated on failure
+    fn allocate_chain(&mut self, count: usize) -> Result<Vec<u32>, FsError> {
+        let mut clusters: Vec<u32> = Vec::with_capacity(count);
+        for _ in 0..count {
+            let res = self.allocate_cluster().and_then(|cluster| {
+                if let Some(&last) = clusters.last() {
+                    self.set_fat_entry(last, cluster)?;
+                }
+                Ok(cluster)
+            });
+
+            match res {
+                Ok(cluster) => clusters.push(cluster),
+                Err(err) => {
+                    if let Some(&first) = clusters.first() {
+                        self.free_chain(first)?;
+                    }
+                    return Err(err);
+                }
+            }
+        }
+        Ok(clusters)
+    }
+
+    fn free_chain(&mut self, first: u32) -> Result<(), FsError> {
+        for cluster in self.chain(first)? {
+            self.set_fat_entry(cluster, FAT_FREE)?;
+            self.next_free = self.next_free.min(cluster);
+        }
+        Ok(())
+    }
+
+    // The last cluster is padded with zeros
+    fn write_clusters(&mut self, clusters: &[u32], data: &[u8]) -> Result<(), FsError> {
+        let cluster_size = self.cluster_size();
+        let mut padded = vec![0u8; cluster_size];
+
+        for (&cluster, chunk) in clusters.iter().zip(data.chunks(cluster_size)) {
+            match chunk.len() == cluster_size {
+                true => self.write_cluster(cluster, chunk)?,
+                false => {
+                    padded[..chunk.len()].copy_from_slice(chunk);
+                    padded[chunk.len()..].fill(0);
+                    self.write_cluster(cluster, &padded)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn read_dir(&mut self, cluster: u32) -> Result<Directory, FsError> {
+        let clusters = self.chain(self.dir_cluster(cluster))?;
+        let cluster_size = self.cluster_size();
+
+        let mut data = vec![0u8; clusters.len() * cluster_size];
+        for (&cluster, chunk) in clusters.iter().zip(data.chunks_mut(cluster_size)) {
+            self.read_cluster(cluster, chunk)?;
+        }
+        Ok(Directory { clusters, data })
+    }
+
+    // Writes back the sectors holding the slots
+    fn write_dir_slots(&mut self, dir: &Directory, slots: Range<usize>) -> Result<(), FsError> {
+        let cluster_size = self.cluster_size();
+        let first_sector = slots.start * DIR_ENTRY_SIZE / BLOCK_SIZE;
+        let last_sector = (slots.end * DIR_ENTRY_SIZE - 1) / BLOCK_SIZE;
+
+        for sector in first_sector..=last_sector {
+            let offset = sector * BLOCK_SIZE;
+            let cluster = dir.clusters[offset / cluster_size];
+            let lba = self.cluster_lba(cluster) + ((offset % cluster_size) / BLOCK_SIZE) as u64;
+            self.write_sectors(lba, &dir.data[offset..offset + BLOCK_SIZE])?;
+        }
+        Ok(())
+    }
+
+    // Adds a zeroed cluster at the end of the directory
+    fn extend_dir(&mut self, dir: &mut Directory) -> Result<(), FsError> {
+        let cluster = self.allocate_cluster()?;
+        let zeros = vec![0u8; self.cluster_size()];
+
+        let res = self.write_cluster(cluster, &zeros).and_then(|()| {
+            let last = *dir.clusters.last().ok_or(FsError::Corrupted)?;
+            self.set_fat_entry(last, cluster)
+        });
+        if let Err(err) = res {
+            self.free_chain(cluster)?;
+            return Err(err);
+        }
+
+        dir.clusters.push(cluster);
+        dir.data.extend_from_slice(&zeros);
+        Ok(())
+    }
+
+    fn add_entry(
+        &mut self,
+        dir: &mut Directory,
+        name: &str,
+        attributes: u8,
+        cluster: u32,
+        size: u32,
+    ) -> Result<(), FsError> {
+        let (short_name, case, long_name) = match exact_short_name(name) {
+            Some((short_name, case)) => (short_name, case, None),
+            None => {
+                let entries = parse_entries(&dir.data);
+                let short_name = generated_short_name(name, &entries)?;
+                let units: Vec<u16> = name.encode_utf16().collect();
+                (short_name, 0, Some(units))
+            }
+        };
+
+        let long_entries = long_name
+            .as_ref()
+            .map_or(0, |units| (units.len() + LFN_CHARS - 1) / LFN_CHARS);
+        let needed = long_entries + 1;
+
+        let start = loop {
+            match find_free_slots(&dir.data, needed) {
+                Some(start) => break start,
+                None => self.extend_dir(dir)?,
+            }
+        };
+
+        let checksum = short_name_checksum(&short_name);
+        let units = long_name.unwrap_or_default();
+        for i in 0..long_entries {
+            let order = long_entries - i;
+            let raw = &mut dir.data[(start + i) * DIR_ENTRY_SIZE..][..DIR_ENTRY_SIZE];
+            raw.fill(0);
+            raw[0] = order as u8 | if i == 0 { LFN_LAST } else { 0 };
+            raw[DIR_ATTRIBUTES] = ATTR_LONG_NAME;
+            raw[LFN_CHECKSUM] = checksum;
+
+            // The name ends with a null character, then padding
+            for (j, &offset) in LFN_CHAR_OFFSETS.iter().enumerate() {
+                let index = (order - 1) * LFN_CHARS + j;
+                let unit = match index.cmp(&units.len()) {
+                    core::cmp::Ordering::Less => units[index],
+                    core::cmp::Ordering::Equal => 0,
+                    core::cmp::Ordering::Greater => LFN_PADDING,
+                };
+                write_u16(raw, offset, unit);
+            }
+        }
+
+        let raw = &mut dir.data[(start + long_entries) * DIR_ENTRY_SIZE..][..DIR_ENTRY_SIZE];
+        raw.fill(0);
+        raw[..SHORT_NAME_LEN].copy_from_slice(&short_name);
+        raw[DIR_ATTRIBUTES] = attributes;
+        raw[DIR_CASE] = case;
+        set_entry_cluster(raw, cluster);
+        write_u32(raw, DIR_SIZE, size);
+        set_write_time(raw);
+        let (time, date) = (read_u16(raw, DIR_WRITE_TIME), read_u16(raw, DIR_WRITE_DATE));
+        write_u16(raw, DIR_CREATION_TIME, time);
+        write_u16(raw, DIR_CREATION_DATE, date);
+
+        self.write_dir_slots(dir, start..start + needed)
+    }
+
+    fn read_fsinfo(&mut self) -> Result<(), FsError> {
+        let lba = match self.fsinfo_sector {
+            Some(lba) => lba,
+            None => return Ok(()),
+        };
+
+        let mut buf = [0u8; BLOCK_SIZE];
+        self.read_sectors(lba, &mut buf)?;
+        let valid = read_u32(&buf, 0) == FSINFO_LEAD_SIGNATURE
+            && read_u32(&buf, FSINFO_STRUCT) == FSINFO_STRUCT_SIGNATURE;
+
+        match valid {
+            true => self.next_free = read_u32(&buf, FSINFO_NEXT_FREE),
+            false => self.fsinfo_sector = None,
+        }
+        Ok(())
+    }
+
+    // Saves the allocation hint and flushes the disk, the free count isn't tracked
+    fn sync(&mut self) -> Result<(), FsError> {
+        if let Some(lba) = self.fsinfo_sector {
+            let mut buf = [0u8; BLOCK_SIZE];
+            self.read_sectors(lba, &mut buf)?;
+            write_u32(&mut buf, FSINFO_FREE_COUNT, FSINFO_UNKNOWN);
+            write_u32(&mut buf, FSINFO_NEXT_FREE, self.next_free);
+            self.write_sectors(lba, &buf)?;
+        }
+
+        Ok(self.disk.flush()?)
+    }
+}
+
+#[cfg(test)]
+struct MemoryDisk {
+    data: Vec<u8>,
+}
+
+#[cfg(test)]
+impl BlockDevice for MemoryDisk {
+    fn name(&self) -> &str {
+        "memory"
+    }
+
+    fn block_count(&self) -> u64 {
+        (self.data.len() / BLOCK_SIZE) as u64
+    }
+
+    fn read_blocks(&mut self, lba: u64, buf: &mut [u8]) -> Result<(), crate::storage::BlockError> {
+        crate::storage::check_request(self.block_count(), lba, buf.len())?;
+        let start = lba as usize * BLOCK_SIZE;
+        buf.copy_from_slice(&self.data[start..start + buf.len()]);
+        Ok(())
+    }
+
+    fn write_blocks(&mut self, lba: u64, buf: &[u8]) -> Result<(), crate::storage::BlockError> {
+        crate::storage::check_request(self.block_count(), lba, buf.len())?;
+        let start = lba as usize * BLOCK_SIZE;
+        self.data[start..start + buf.len()].copy_from_slice(buf);
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), crate::storage::BlockError> {
+        Ok(())
+    }
+}
+
+#[test_case]
+fn test_short_names() {
+    let lower = CASE_LOWER_BASE | CASE_LOWER_EXTENSION;
+    assert_eq!(exact_short_name("settings.cfg"), Some((*b"SETTINGSCFG", lower)));
+    assert_eq!(exact_short_name("README"), Some((*b"README     ", 0)));
+    assert_eq!(exact_short_name("Game.pgn"), None);
+    assert_eq!(exact_short_name("long name.txt"), None);
+
+    let short_name = generated_short_name("A long game.pgn", &[]);
+    assert_eq!(short_name, Ok(*b"ALONGG~1PGN"));
+    assert_eq!(short_name_to_string(b"SETTINGSCFG", lower), "settings.cfg");
+}
+
+#[test_case]
+fn test_files_and_directories() {
+    let mut small = MemoryDisk {
+        data: vec![0; 2 * 1024 * 1024],
+    };
+    assert_eq!(Fat32::format(&mut small), Err(FsError::TooSmall));
+    drop(small);
+
+    // About the smallest disk that has enough clusters for FAT32
+    let mut disk = MemoryDisk {
+        data: vec![0; 34 * 1024 * 1024],
+    };
+    Fat32::format(&mut disk).unwrap();
+    let mut fs = Fat32::mount(&mut disk).unwrap();
+
+    fs.create_dir("/games").unwrap();
+    assert_eq!(fs.create_dir("/games"), Err(FsError::AlreadyExists));
+    fs.write_file("/games/A long game name.pgn", b"1. e4 e5").unwrap();
+    fs.write_file("/settings.cfg", &[7; 3000]).unwrap();
+
+    assert_eq!(fs.read_file("/GAMES/a long game name.pgn").unwrap(), b"1. e4 e5");
+    assert_eq!(fs.read_file("/settings.cfg").unwrap(), [7; 3000]);
+
+    let names: Vec<String> = fs.list_dir("/games").unwrap().into_iter().map(|e| e.name).collect();
+    assert_eq!(names, ["A long game name.pgn"]);
+
+    // Replacing frees the old clusters
+    fs.write_file("/settings.cfg", b"short").unwrap();
+    assert_eq!(fs.read_file("/settings.cfg").unwrap(), b"short");
+
+    assert_eq!(fs.remove("/games"), Err(FsError::NotEmpty));
+    fs.remove("/games/A long game name.pgn").unwrap();
+    fs.remove("/games").unwrap();
+    assert_eq!(fs.read_file("/games/x"), Err(FsError::NotFound));
+    assert_eq!(fs.list_dir("/").unwrap().len(), 1);
+}
@@ -0,0 +1,105 @@
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::{
+    info,
+    storage::{self, BlockDevice, BlockError, BLOCK_SIZE},
+    warn,
+};
+
+pub mod fat32;
+
+pub use fat32::{DirEntry, Fat32};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FsError {
+    NoDisk,
+    NotFat32,
+    NotFound,
+    NotADirectory,
+    IsADirectory,
+    NotEmpty,
+    AlreadyExists,
+    InvalidName,
+    FileTooLarge,
+    DiskFull,
+    TooSmall, // Can't be formatted as FAT32
+    Corrupted,
+    Disk(BlockError),
+}
+
+impl From<BlockError> for FsError {
+    fn from(err: BlockError) -> Self {
+        FsError::Disk(err)
+    }
+}
+
+impl fmt::Display for FsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FsError::NoDisk => write!(f, "no disk"),
+            FsError::NotFat32 => write!(f, "not a FAT32 filesystem"),
+            FsError::NotFound => write!(f, "not found"),
+            FsError::NotADirectory => write!(f, "not a directory"),
+            FsError::IsADirectory => write!(f, "is a directory"),
+            FsError::NotEmpty => write!(f, "directory not empty"),
+            FsError::AlreadyExists => write!(f, "already exists"),
+            FsError::InvalidName => write!(f, "invalid name"),
+            FsError::FileTooLarge => write!(f, "file too large"),
+            FsError::DiskFull => write!(f, "disk full"),
+            FsError::TooSmall => write!(f, "disk too small for FAT32"),
+            FsError::Corrupted => write!(f, "filesystem corrupted"),
+            FsError::Disk(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+// A blank disk is formatted, so a new disk image works without preparing it on the host
+pub fn init() {
+    let res = storage::with_disk(|disk| {
+        let mounted = Fat32::mount(disk).map(|_| ());
+        match mounted {
+            Ok(()) => Ok(false),
+            Err(FsError::NotFat32) if is_blank(disk)? => Fat32::format(disk).map(|()| true),
+            Err(err) => Err(err),
+        }
+    });
+
+    match res {
+        None => {}
+        Some(Ok(false)) => info!("FAT32 filesystem mounted"),
+        Some(Ok(true)) => info!("Blank disk formatted as FAT32"),
+        Some(Err(err)) => warn!("Disk can't be used: {}", err),
+    }
+}
+
+fn is_blank(disk: &mut dyn BlockDevice) -> Result<bool, FsError> {
+    let mut sector = [0u8; BLOCK_SIZE];
+    disk.read_blocks(0, &mut sector)?;
+    Ok(sector.iter().all(|&byte| byte == 0))
+}
+
+// The filesystem of the first disk, mounted for the duration of `f`
+pub fn with_fs<R>(f: impl FnOnce(&mut Fat32) -> Result<R, FsError>) -> Result<R, FsError> {
+    storage::with_disk(|disk| f(&mut Fat32::mount(disk)?)).unwrap_or(Err(FsError::NoDisk))
+}
+
+pub fn read_file(path: &str) -> Result<Vec<u8>, FsError> {
+    with_fs(|fs| fs.read_file(path))
+}
+
+pub fn write_file(path: &str, data: &[u8]) -> Result<(), FsError> {
+    with_fs(|fs| fs.write_file(path, data))
+}
+
+pub fn list_dir(path: &str) -> Result<Vec<DirEntry>, FsError> {
+    with_fs(|fs| fs.list_dir(path))
+}
+
+pub fn create_dir(path: &str) -> Result<(), FsError> {
+    with_fs(|fs| fs.create_dir(path))
+}
+
+pub fn remove(path: &str) -> Result<(), FsError> {
+    with_fs(|fs| fs.remove(path))
+}
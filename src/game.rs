@@ -1,6 +1,7 @@
-use alloc::{
-    boxed::Box,
-    vec::{Vec},
+use alloc::{boxed::Box, format, vec, vec::Vec};
+use core::{
+    sync::atomic::{AtomicBool, AtomicI16, Ordering},
+    time::Duration,
 };
 use arrayvec::ArrayVec;
 use cozy_chess::{Board, Move, Square};
@@ -12,33 +13,63 @@ use engine::{
     Eval,
 };
 use pc_keyboard::DecodedKey;
-use ps2_mouse::MouseState;
-use x86_64::instructions::hlt;
 
 use crate::{
+    allocator,
     display::{
         color::Color256,
-        graphics::{clear_buffer, draw_sprite, flush_buffer, Rectangle, HEIGHT, WIDTH},
+        graphics::{clear_buffer, flush_buffer, flush_buffer_with, Rectangle, HEIGHT, WIDTH},
         sprite::Sprite,
     },
     entities::{
         button::Button,
         chessboard::{is_checkmate, ChessBoard, BOARD_X, BOARD_Y, BORDER_SIZE},
+        clock::Clock,
         colorselector::ColorSelector,
         difficultyselector::DifficultySelector,
         engineeval::EngineEval,
         enginethinking::EngineThinking,
+        moveinput::MoveInput,
+        movelist::MoveList,
         promotion::PromotionDisplayer,
+        settingsselector::SettingsSelector,
         text::Text,
     },
-    events::add_event,
-    load_sprite,
+    events::{self, post_event},
+    error, info, load_sprite,
+    pgn::{self, Pgn},
+    power,
+    ps2::{self, MouseState},
+    rtc,
+    savegame::{self, SavedGame},
+    settings::{self, Settings, Theme},
+    speaker,
+    task::executor,
+    warn,
 };
 
-const MOUSE_WIDTH: usize = 7;
-const MOUSE_HEIGHT: usize = 10;
+pub const MOUSE_WIDTH: usize = 7;
+pub const MOUSE_HEIGHT: usize = 10;
 
-const MOUSE: Sprite = load_sprite!("../sprites/Mouse.data", MOUSE_WIDTH);
+pub const MOUSE: Sprite = load_sprite!("../sprites/Mouse.data", MOUSE_WIDTH);
+
+// Pixels the cursor has to move with the left button down before it is a drag and not a click
+const DRAG_THRESHOLD: i16 = 3;
+
+// Room left for the game in the engine's history, the rest is for the plies of its search
+const ENGINE_HISTORY: usize = 256 - MAX_DEPTH as usize;
+
+// Played on every move when the sound is on
+const MOVE_SOUND_FREQUENCY: u32 = 880;
+const MOVE_SOUND_DURATION: Duration = Duration::from_millis(30);
+
+// Each depth is searched inside `handle_event`, only the cursor task runs meanwhile. Clicks, keys
+// and ticks wait in the queue until the depth is done and are handled before the next one
+static ENGINE_SEARCHING: AtomicBool = AtomicBool::new(false);
+
+// Where the game last drew the cursor, for the cursor task to continue from
+static CURSOR_X: AtomicI16 = AtomicI16::new(0);
+static CURSOR_Y: AtomicI16 = AtomicI16::new(0);
 
 struct Handler {
     res: Option<engine::SearchResult>,
@@ -61,23 +92,46 @@ pub trait Entity {
     fn to_delete(&self, shared: &Shareable) -> bool;
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MouseButton {
+    Left,
+    Right,
+    Middle,
+}
+
 #[derive(Debug, Clone)]
 pub enum Event {
-    MouseInput(MouseState),
+    MouseInput(MouseState), // Raw state, turned into the mouse events below by the game
     KeyboardInput(DecodedKey),
+    MousePress(MouseButton),
+    MouseRelease(MouseButton),
+    MouseMove,
+    DragStart(i16, i16), // Where the left button was pressed
+    DragEnd,
+    Wheel(i8), // Negative when scrolling up
     StartGame,
+    ContinueGame, // Resumes the game saved on disk
+    LoadPgn,      // Plays on from the game in the PGN file to import
     EndGame,
     ReturnToMenu,
-    Exit,
+    Shutdown,
+    Restart,
     PlayMove(cozy_chess::Move),
     DisplayPromotion(cozy_chess::Square, cozy_chess::Square), // From to dest Square for the struct
     StartEngineSearch(u8),                                    // depth
     SetPlayerColor(cozy_chess::Color),
     SetEngineDepth(u8),
+    NextTheme,
+    ToggleSound,
+    Tick, // Sent every second
 }
 
 pub struct Shareable {
     pub board: Board,
+    pub start: Board, // Where the moves were played from
+    pub moves: Vec<Move>, // Played since the start of the game
+    // Earlier position looked at with the wheel, and how many moves were played to reach it
+    pub viewed: Option<(usize, Board)>,
     pub mouse_x: i16,
     pub mouse_y: i16,
     pub state: State,
@@ -86,19 +140,36 @@ pub struct Shareable {
     pub engine_thinking: bool,
     pub user_color: cozy_chess::Color,
     pub engine_depth: u8,
+    pub theme: Theme,
+    pub sound: bool,
 }
 
 impl Shareable {
     pub fn should_flip(&self) -> bool {
         self.user_color == cozy_chess::Color::Black
     }
+
+    pub fn displayed_board(&self) -> &Board {
+        self.viewed.as_ref().map_or(&self.board, |(_, board)| board)
+    }
 }
 
 pub struct Game<'a> {
     shared: Shareable,
     engine: Engine<'a, Handler>,
     history: Vec<u64>,
+    // What the engine thought of each of its moves, None for the user's, written in the PGN
+    evals: Vec<Option<Eval>>,
     entities: Vec<Box<dyn Entity>>,
+    last_mouse_state: MouseState,
+    // Where the left button was pressed, and if it moved far enough since to be a drag
+    press_position: Option<(i16, i16)>,
+    dragging: bool,
+    // Asked for in the settings, the table might be smaller
+    tt_size_kb: usize,
+    // Checked on the disk at boot then kept up to date, so the menu doesn't read the files
+    has_saved_game: bool,
+    has_import: bool,
 }
 
 impl SearchHandler for Handler {
@@ -111,42 +182,100 @@ impl SearchHandler for Handler {
             .map(|res| res.stats.depth >= self.current_depth)
             .unwrap_or(false)
     }
+
+    fn yield_now(&mut self) {
+        executor::yield_now();
+    }
+}
+
+// Halving the size until it fits, a smaller table only makes the engine weaker
+fn allocate_tt(wanted_kb: usize) -> TranspositionTable {
+    let mut size_kb = wanted_kb;
+    loop {
+        match TranspositionTable::try_new(TableSize::from_kb(size_kb)) {
+            Ok(tt) => {
+                if size_kb != wanted_kb {
+                    warn!("Transposition table reduced to {} KiB", size_kb);
+                }
+                return tt;
+            }
+            Err(_) if size_kb > *settings::TT_SIZES_KB.start() => size_kb /= 2,
+            Err(_) => return TranspositionTable::new(TableSize::from_kb(size_kb)),
+        }
+    }
+}
+
+// The engine keeps the history in a fixed size array, along with the positions of its search.
+// Positions before the last capture or pawn move can't be repeated, so they are left out
+pub fn engine_history<'h>(history: &'h [u64], board: &Board) -> &'h [u64] {
+    let len = (board.halfmove_clock() as usize)
+        .min(ENGINE_HISTORY)
+        .min(history.len());
+    &history[history.len() - len..]
+}
+
+pub fn is_engine_searching() -> bool {
+    ENGINE_SEARCHING.load(Ordering::Relaxed)
+}
+
+pub fn cursor_position() -> (i16, i16) {
+    (CURSOR_X.load(Ordering::Relaxed), CURSOR_Y.load(Ordering::Relaxed))
+}
+
+pub fn move_cursor(x: i16, y: i16, state: &MouseState) -> (i16, i16) {
+    let x = (x + state.get_x()).clamp(0, (WIDTH - MOUSE_WIDTH) as i16);
+    let y = (y - state.get_y()).clamp(0, (HEIGHT - MOUSE_HEIGHT) as i16);
+    (x, y)
 }
 
 impl<'a> Game<'a> {
     pub fn new() -> Self {
         let board = Board::default();
+        let settings = settings::load();
+        let tt = allocate_tt(settings.tt_size_kb);
         let options = EngineOptions {
-            tt_size: TableSize::from_kb(10),
+            tt_size: tt.table_size(),
             depth: 128,
         };
         let search_shared = SearchSharedState {
             handler: Handler {
                 res: None,
                 current_depth: 1,
-                max_depth: 3,
+                max_depth: settings.engine_level,
             },
             history: ArrayVec::new(),
-            tt: TranspositionTable::new(TableSize::from_kb(10)),
+            tt,
             killers: [[None; 2]; MAX_DEPTH as usize],
         };
         let shared = Shareable {
             board: board.clone(),
+            start: board.clone(),
+            moves: Vec::new(),
+            viewed: None,
             mouse_x: 0,
             mouse_y: 0,
             state: State::Menu,
             in_promotion: false,
             engine_eval: Eval::NEUTRAL,
             engine_thinking: false,
-            user_color: cozy_chess::Color::White,
+            user_color: settings.user_color,
             engine_depth: search_shared.handler.max_depth,
+            theme: settings.theme,
+            sound: settings.sound,
         };
         let engine = Engine::new(board, options, search_shared);
         Self {
             shared,
             history: Vec::new(),
+            evals: Vec::new(),
             engine,
             entities: Vec::new(),
+            last_mouse_state: MouseState::default(),
+            press_position: None,
+            dragging: false,
+            tt_size_kb: settings.tt_size_kb,
+            has_saved_game: savegame::load().is_some(),
+            has_import: pgn::import().is_some(),
         }
     }
 
@@ -166,20 +295,40 @@ impl<'a> Game<'a> {
 
         match event {
             Event::MouseInput(state) => self.handle_mouse_input(state),
-            Event::KeyboardInput(_) => {}
+            Event::KeyboardInput(key) => self.handle_keyboard_input(key),
+            Event::Wheel(delta) => self.step_history(*delta),
             Event::StartGame => self.start_game(),
+            Event::ContinueGame => self.continue_game(),
+            Event::LoadPgn => self.load_pgn(),
             Event::EndGame => self.end_game(),
             Event::ReturnToMenu => self.return_to_menu(),
             Event::PlayMove(mv) => self.play_move(*mv),
             Event::DisplayPromotion(from, to) => self.display_promotion(*from, *to),
-            Event::Exit => loop {
-                hlt()
-            },
+            Event::Shutdown => power::shutdown(),
+            Event::Restart => power::reboot(),
             Event::StartEngineSearch(depth) => self.start_engine_search(*depth),
-            Event::SetPlayerColor(color) => self.shared.user_color = *color,
+            Event::SetPlayerColor(color) => {
+                self.shared.user_color = *color;
+                self.save_settings();
+            }
             Event::SetEngineDepth(depth) => {
                 self.shared.engine_depth = *depth;
+                self.save_settings();
+            }
+            Event::NextTheme => {
+                self.shared.theme = self.shared.theme.next();
+                self.save_settings();
             }
+            Event::ToggleSound => {
+                self.shared.sound = !self.shared.sound;
+                self.save_settings();
+            }
+            Event::MousePress(_)
+            | Event::MouseRelease(_)
+            | Event::MouseMove
+            | Event::DragStart(..)
+            | Event::DragEnd
+            | Event::Tick => {}
         }
     }
 
@@ -188,83 +337,306 @@ impl<'a> Game<'a> {
         for entity in self.entities.iter() {
             entity.draw(&self.shared);
         }
-        draw_sprite(
+        if !ps2::has_mouse() {
+            flush_buffer();
+            return;
+        }
+
+        // The cursor is kept out of the buffer so it can be redrawn alone during a search
+        flush_buffer_with(
             &MOUSE,
             self.shared.mouse_x as usize,
             self.shared.mouse_y as usize,
         );
-        flush_buffer();
     }
 
+    // Entities only get the changes between two states, so holding a button is a single press.
+    // They are handled right away, before the next raw state, and not queued: the game is the
+    // only one emptying the queue and must never wait for room in it
     fn handle_mouse_input(&mut self, state: &MouseState) {
-        self.shared.mouse_x += state.get_x();
-        self.shared.mouse_y -= state.get_y();
+        let (x, y) = move_cursor(self.shared.mouse_x, self.shared.mouse_y, state);
+        if (x, y) != (self.shared.mouse_x, self.shared.mouse_y) {
+            (self.shared.mouse_x, self.shared.mouse_y) = (x, y);
+            CURSOR_X.store(x, Ordering::Relaxed);
+            CURSOR_Y.store(y, Ordering::Relaxed);
+            self.handle_event(&Event::MouseMove);
+        }
+
+        let last = self.last_mouse_state;
+        self.last_mouse_state = *state;
+
+        let buttons = [
+            (MouseButton::Left, state.left_button_down(), last.left_button_down()),
+            (MouseButton::Right, state.right_button_down(), last.right_button_down()),
+            (MouseButton::Middle, state.middle_button_down(), last.middle_button_down()),
+        ];
+        for (button, down, was_down) in buttons {
+            match (down, was_down) {
+                (true, false) => self.handle_event(&Event::MousePress(button)),
+                (false, true) => self.handle_event(&Event::MouseRelease(button)),
+                _ => {}
+            }
+        }
+
+        match (state.left_button_down(), self.press_position) {
+            (true, None) => self.press_position = Some((x, y)),
+            (true, Some((press_x, press_y))) => {
+                let moved = (x - press_x).abs().max((y - press_y).abs());
+                if !self.dragging && moved >= DRAG_THRESHOLD {
+                    self.dragging = true;
+                    self.handle_event(&Event::DragStart(press_x, press_y));
+                }
+            }
+            (false, _) => {
+                if self.dragging {
+                    self.handle_event(&Event::DragEnd);
+                }
+                self.press_position = None;
+                self.dragging = false;
+            }
+        }
+
+        if state.wheel() != 0 {
+            self.handle_event(&Event::Wheel(state.wheel()));
+        }
+    }
 
-        self.shared.mouse_x = self.shared.mouse_x.clamp(0, (WIDTH - MOUSE_WIDTH) as i16);
-        self.shared.mouse_y = self.shared.mouse_y.clamp(0, (HEIGHT - MOUSE_HEIGHT) as i16);
+    // Moves back and forth through the positions of the game, playing stays on the latest one
+    fn step_history(&mut self, delta: i8) {
+        if self.shared.state == State::Menu {
+            return;
+        }
+
+        let latest = self.shared.moves.len();
+        let current = self.shared.viewed.as_ref().map_or(latest, |(ply, _)| *ply);
+        let ply = (current as isize + delta as isize).clamp(0, latest as isize) as usize;
+
+        self.shared.viewed = match ply == latest {
+            true => None,
+            false => {
+                let mut board = self.shared.start.clone();
+                for &mv in &self.shared.moves[..ply] {
+                    board.play_unchecked(mv);
+                }
+                Some((ply, board))
+            }
+        };
+    }
+
+    // Menu shortcuts so the game can be played without a mouse, moves are typed in `MoveInput`
+    fn handle_keyboard_input(&mut self, key: &DecodedKey) {
+        let c = match key {
+            DecodedKey::Unicode(c) => *c,
+            DecodedKey::RawKey(_) => return,
+        };
+
+        match (&self.shared.state, c) {
+            (State::Menu, '\n') => post_event(Event::StartGame),
+            (State::Menu, 'c') => post_event(Event::ContinueGame),
+            (State::Menu, 'l') => post_event(Event::LoadPgn),
+            (State::Menu, '1'..='7') => post_event(Event::SetEngineDepth(c as u8 - b'0')),
+            (State::Menu, 'w') => post_event(Event::SetPlayerColor(cozy_chess::Color::White)),
+            (State::Menu, 'b') => post_event(Event::SetPlayerColor(cozy_chess::Color::Black)),
+            (State::Menu, 't') => post_event(Event::NextTheme),
+            (State::Menu, 's') => post_event(Event::ToggleSound),
+            (State::GameOver, '\n') => post_event(Event::ReturnToMenu),
+            _ => {}
+        }
     }
 
     fn play_move(&mut self, mv: Move) {
         self.shared.in_promotion = false;
 
+        let engine_move = self.shared.board.side_to_move() != self.shared.user_color;
+        self.evals.push(engine_move.then_some(self.shared.engine_eval));
+        self.shared.moves.push(mv);
+        self.shared.viewed = None;
+
+        if self.shared.sound {
+            executor::spawn(speaker::beep(MOVE_SOUND_FREQUENCY, MOVE_SOUND_DURATION));
+        }
+
         let board = &mut self.shared.board;
         self.history.push(board.hash());
         board.play(mv);
 
         if board.side_to_move() != self.shared.user_color && !is_checkmate(board) {
-            self.engine.set_position(board.clone(), &self.history);
+            let history = engine_history(&self.history, board);
+            self.engine.set_position(board.clone(), history);
             self.engine.mut_handler().res = None;
-            add_event(Event::StartEngineSearch(1))
+            post_event(Event::StartEngineSearch(1))
         }
+
+        self.save_game();
+    }
+
+    // After every move so closing the VM mid-game loses nothing, a finished game is deleted
+    fn save_game(&mut self) {
+        self.has_saved_game = savegame::save(&SavedGame {
+            start: self.shared.start.clone(),
+            moves: self.shared.moves.clone(),
+            engine_level: self.shared.engine_depth,
+            user_color: self.shared.user_color,
+        });
+    }
+
+    // Written on every change, the settings are small and changed rarely
+    fn save_settings(&self) {
+        settings::save(&Settings {
+            engine_level: self.shared.engine_depth,
+            user_color: self.shared.user_color,
+            theme: self.shared.theme,
+            sound: self.shared.sound,
+            tt_size_kb: self.tt_size_kb,
+        });
     }
 
     fn display_promotion(&mut self, from: Square, to: Square) {
         if !self.shared.in_promotion {
-            self.shared.in_promotion = true;
-            self.entities
-                .push(Box::new(PromotionDisplayer::new(from, to)));
+            self.shared.in_promotion = self.add_entity(PromotionDisplayer::new(from, to));
         }
     }
 
     fn start_engine_search(&mut self, depth: u8) {
-        if depth == 1 {
-            self.engine
-                .set_position(self.shared.board.clone(), &self.history);
-            self.engine.mut_handler().max_depth = self.shared.engine_depth;
+        match depth {
+            1 => {
+                let history = engine_history(&self.history, &self.shared.board);
+                self.engine.set_position(self.shared.board.clone(), history);
+                self.engine.mut_handler().max_depth = self.shared.engine_depth;
+                self.shared.engine_thinking = true;
+            }
+            // The game was left between two depths
+            _ if !self.shared.engine_thinking => return,
+            _ => {}
         }
-        self.shared.engine_thinking = true;
 
         self.engine.mut_handler().current_depth = depth + 1;
+        ENGINE_SEARCHING.store(true, Ordering::Relaxed);
         self.engine.best_move_starting(depth);
-        self.shared.engine_eval = self.engine.handler().res.unwrap().eval;
+        ENGINE_SEARCHING.store(false, Ordering::Relaxed);
+        let res = self.engine.handler().res.unwrap();
+        self.shared.engine_eval = res.eval;
+
+        info!(
+            "Engine depth {}: best move {}, eval {:?}, {} nodes, seldepth {}",
+            res.stats.depth,
+            res.best_move,
+            res.eval,
+            res.stats.nodes_visited,
+            res.stats.sel_depth
+        );
 
         if depth >= self.engine.handler().max_depth || self.shared.engine_eval.is_mate() {
             let mv = self.engine.handler().res.unwrap().best_move;
-            add_event(Event::PlayMove(mv));
+            post_event(Event::PlayMove(mv));
             self.shared.engine_thinking = false;
             return;
         }
 
-        add_event(Event::StartEngineSearch(depth + 1))
+        // So a click on a button isn't kept waiting for the whole search
+        self.handle_pending_input();
+        post_event(Event::StartEngineSearch(depth + 1))
+    }
+
+    fn handle_pending_input(&mut self) {
+        while let Some(event) = events::try_next_input() {
+            self.handle_event(&event);
+            self.draw();
+        }
+    }
+
+    // Entities that can't be allocated are skipped so running out of memory doesn't end the game
+    fn add_entity<E: Entity + 'static>(&mut self, entity: E) -> bool {
+        match (self.entities.try_reserve(1), Box::try_new(entity)) {
+            (Ok(()), Ok(entity)) => {
+                self.entities.push(entity);
+                true
+            }
+            _ => {
+                error!("Out of memory, skipping an entity");
+                false
+            }
+        }
     }
 
     fn start_game(&mut self) {
-        self.entities.clear();
         self.shared.board = Board::default();
-        self.shared.state = State::InGame;
-        self.shared.engine_eval = Eval::NEUTRAL;
-        self.engine.mut_handler().res = None;
+        self.shared.start = Board::default();
+        self.shared.moves.clear();
+        self.evals.clear();
         self.history.clear();
-
-        self.entities.push(Box::new(ChessBoard::new()));
-        self.entities.push(Box::new(EngineEval::new()));
-        self.entities.push(Box::new(EngineThinking));
+        self.enter_game();
+        self.save_game();
 
         if self.shared.should_flip() {
-            add_event(Event::StartEngineSearch(1))
+            post_event(Event::StartEngineSearch(1))
+        }
+    }
+
+    fn continue_game(&mut self) {
+        match savegame::load() {
+            Some(saved) => {
+                info!("Continuing the saved game after {} moves", saved.moves.len());
+                self.resume(saved);
+            }
+            None => self.has_saved_game = false,
         }
     }
 
+    // The imported game replaces the saved one, with the colors and level from the menu
+    fn load_pgn(&mut self) {
+        let pgn = match pgn::import() {
+            Some(pgn) => pgn,
+            None => {
+                self.has_import = false;
+                return;
+            }
+        };
+        info!("Loaded {} moves from {}", pgn.moves.len(), pgn::IMPORT_PATH);
+
+        self.resume(SavedGame {
+            start: pgn.start,
+            moves: pgn.moves,
+            engine_level: self.shared.engine_depth,
+            user_color: self.shared.user_color,
+        });
+        self.evals = pgn.evals;
+        self.save_game();
+    }
+
+    fn resume(&mut self, saved: SavedGame) {
+        let (board, history) = saved.replay();
+        self.shared.board = board;
+        self.shared.start = saved.start;
+        self.shared.moves = saved.moves;
+        self.shared.user_color = saved.user_color;
+        self.shared.engine_depth = saved.engine_level;
+        self.evals = vec![None; self.shared.moves.len()];
+        self.history = history;
+        self.enter_game();
+
+        let board = &self.shared.board;
+        if board.side_to_move() != self.shared.user_color && !is_checkmate(board) {
+            post_event(Event::StartEngineSearch(1))
+        }
+    }
+
+    // Shared by a new and a continued game, the position is already set
+    fn enter_game(&mut self) {
+        self.entities.clear();
+        self.shared.viewed = None;
+        self.shared.state = State::InGame;
+        self.shared.engine_thinking = false;
+        self.shared.engine_eval = Eval::NEUTRAL;
+        self.engine.mut_handler().res = None;
+
+        self.add_entity(ChessBoard::new());
+        self.add_entity(EngineEval::new());
+        self.add_entity(EngineThinking);
+        self.add_entity(MoveInput::new());
+        self.add_entity(MoveList::from_game(&self.shared.start, &self.shared.moves));
+    }
+
     fn end_game(&mut self) {
         const GAME_OVER: Rectangle = Rectangle {
             x: BOARD_X - BORDER_SIZE,
@@ -280,11 +652,7 @@ impl<'a> Game<'a> {
             height: 16,
         };
 
-        self.entities.push(Box::new(Button::with_text(
-            GAME_OVER,
-            "GAME OVER",
-            Event::ReturnToMenu,
-        )));
+        self.add_entity(Button::with_text(GAME_OVER, "GAME OVER", Event::ReturnToMenu));
 
         let (text, color) = match self.shared.board.side_to_move() == self.shared.user_color {
             true => ("YOU LOSE", Color256::RED),
@@ -294,39 +662,100 @@ impl<'a> Game<'a> {
         let mut text = Text::new(GAME_RESULT, text);
         text.set_color(color);
 
-        self.entities.push(Box::new(text));
+        self.add_entity(text);
 
         self.shared.state = State::GameOver;
+        self.export_pgn();
+        savegame::delete();
+        self.has_saved_game = false;
+    }
+
+    fn export_pgn(&self) {
+        let date = rtc::now();
+        let mut pgn = Pgn::new(
+            self.shared.start.clone(),
+            self.shared.moves.clone(),
+            self.evals.clone(),
+            date,
+        );
+
+        let engine = format!("BMC-OS level {}", self.shared.engine_depth);
+        let (white, black) = match self.shared.user_color {
+            cozy_chess::Color::White => ("Player", engine.as_str()),
+            cozy_chess::Color::Black => (engine.as_str(), "Player"),
+        };
+        pgn.set_tag("White", white);
+        pgn.set_tag("Black", black);
+
+        pgn::export(&pgn, date);
     }
 
     fn return_to_menu(&mut self) {
         self.entities.clear();
+        info!("{}", allocator::stats());
 
-        const START: Rectangle = Rectangle {
-            x: (WIDTH - 80) / 2,
-            y: 128,
+        const SHUTDOWN: Rectangle = Rectangle {
+            x: WIDTH / 2 - 84,
+            y: 192,
             width: 80,
             height: 32,
         };
 
-        const EXIT: Rectangle = Rectangle {
-            x: (WIDTH - 80) / 2,
+        const RESTART: Rectangle = Rectangle {
+            x: WIDTH / 2 + 4,
             y: 192,
             width: 80,
             height: 32,
         };
 
-        let mut start = Button::with_text(START, "Start", Event::StartGame);
-        start.set_color(Color256::GREEN);
+        let mut shutdown = Button::with_text(SHUTDOWN, "Shut down", Event::Shutdown);
+        shutdown.set_color(Color256::RED);
+
+        let mut restart = Button::with_text(RESTART, "Restart", Event::Restart);
+        restart.set_color(Color256::RED);
 
-        let mut exit = Button::with_text(EXIT, "Exit", Event::Exit);
-        exit.set_color(Color256::RED);
+        self.add_entity(DifficultySelector::new());
+        self.add_entity(ColorSelector::new());
+        self.add_entity(SettingsSelector::new(&self.shared));
 
-        self.entities.push(Box::new(DifficultySelector::new()));
-        self.entities.push(Box::new(ColorSelector::new()));
-        self.entities.push(Box::new(start));
-        self.entities.push(Box::new(exit));
+        // Centered on one row, Continue and Load PGN only when there is a game to load
+        let mut games = vec![("Start", Event::StartGame)];
+        if self.has_saved_game {
+            games.push(("Continue", Event::ContinueGame));
+        }
+        if self.has_import {
+            games.push(("Load PGN", Event::LoadPgn));
+        }
+        let row_width = games.len() * 88 - 8;
+        for (i, (text, event)) in games.into_iter().enumerate() {
+            let rect = Rectangle {
+                x: (WIDTH - row_width) / 2 + i * 88,
+                y: 128,
+                width: 80,
+                height: 32,
+            };
+            let mut button = Button::with_text(rect, text, event);
+            button.set_color(Color256::GREEN);
+            self.add_entity(button);
+        }
+        self.add_entity(shutdown);
+        self.add_entity(restart);
+        self.add_entity(Clock::new());
+
+        if !ps2::has_mouse() {
+            const NO_MOUSE: Rectangle = Rectangle {
+                x: 8,
+                y: 168,
+                width: WIDTH - 16,
+                height: 16,
+            };
+
+            let mut notice = Text::new(NO_MOUSE, "No mouse: Enter, c, l, 1-7, w/b, t, s");
+            notice.set_color(Color256::new(255, 255, 0));
+            self.add_entity(notice);
+        }
 
         self.shared.state = State::Menu;
+        self.shared.engine_thinking = false;
     }
 }
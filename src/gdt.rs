@@ -1,21 +1,53 @@
+use core::mem::size_of;
+
 use lazy_static::lazy_static;
 use x86_64::structures::gdt::{Descriptor, GlobalDescriptorTable, SegmentSelector};
+use x86_64::structures::paging::Page;
 use x86_64::structures::tss::TaskStateSegment;
 use x86_64::VirtAddr;
 
+use crate::memory;
+
 pub const DOUBLE_FAULT_IST_INDEX: u16 = 0;
+// The page fault handler needs its own stack to be able to report kernel stack overflows
+pub const PAGE_FAULT_IST_INDEX: u16 = 1;
+
+const PAGE_SIZE: usize = 4096;
+const IST_STACK_SIZE: usize = PAGE_SIZE * 5;
+
+// Set by the bootloader, must match `kernel-stack-address` and `kernel-stack-size` in Cargo.toml
+const KERNEL_STACK_START: u64 = 0x7f00_0000_0000;
+const KERNEL_STACK_SIZE: u64 = 512 * PAGE_SIZE as u64;
+
+// The first page is the guard page, it gets unmapped by `init_guard_pages`
+#[repr(C, align(4096))]
+struct Stack([u8; PAGE_SIZE + IST_STACK_SIZE]);
+
+static mut DOUBLE_FAULT_STACK: Stack = Stack([0; PAGE_SIZE + IST_STACK_SIZE]);
+static mut PAGE_FAULT_STACK: Stack = Stack([0; PAGE_SIZE + IST_STACK_SIZE]);
+
+impl Stack {
+    fn guard_page(&self) -> Page {
+        Page::containing_address(VirtAddr::from_ptr(self))
+    }
+
+    // Without the guard page
+    fn start(&self) -> VirtAddr {
+        VirtAddr::from_ptr(self) + PAGE_SIZE
+    }
+
+    fn end(&self) -> VirtAddr {
+        VirtAddr::from_ptr(self) + size_of::<Self>()
+    }
+}
 
 lazy_static! {
     static ref TSS: TaskStateSegment = {
         let mut tss = TaskStateSegment::new();
-        tss.interrupt_stack_table[DOUBLE_FAULT_IST_INDEX as usize] = {
-            const STACK_SIZE: usize = 4096 * 5;
-            static mut STACK: [u8; STACK_SIZE] = [0; STACK_SIZE];
-
-            let stack_start = VirtAddr::from_ptr(unsafe { &STACK });
-            let stack_end = stack_start + STACK_SIZE;
-            stack_end
-        };
+        tss.interrupt_stack_table[DOUBLE_FAULT_IST_INDEX as usize] =
+            unsafe { DOUBLE_FAULT_STACK.end() };
+        tss.interrupt_stack_table[PAGE_FAULT_IST_INDEX as usize] =
+            unsafe { PAGE_FAULT_STACK.end() };
         tss
     };
 }
@@ -50,3 +82,57 @@ pub fn init() {
         load_tss(GDT.1.tss_selector);
     }
 }
+
+fn guard_pages() -> [(&'static str, Page); 3] {
+    unsafe {
+        [
+            ("kernel", Page::containing_address(VirtAddr::new(KERNEL_STACK_START))),
+            ("double fault", DOUBLE_FAULT_STACK.guard_page()),
+            ("page fault", PAGE_FAULT_STACK.guard_page()),
+        ]
+    }
+}
+
+// Needs `memory::init` to have been called. The kernel stack guard page is already left
+// unmapped by the bootloader
+pub fn init_guard_pages() {
+    for (name, page) in guard_pages() {
+        if name != "kernel" {
+            memory::unmap_guard_page(page);
+        }
+    }
+}
+
+// Whether the address is in the usable part of one of the stacks
+pub fn is_stack_address(address: VirtAddr) -> bool {
+    let kernel_start = VirtAddr::new(KERNEL_STACK_START) + PAGE_SIZE;
+    let kernel_end = kernel_start + KERNEL_STACK_SIZE;
+
+    unsafe {
+        [
+            (kernel_start, kernel_end),
+            (DOUBLE_FAULT_STACK.start(), DOUBLE_FAULT_STACK.end()),
+            (PAGE_FAULT_STACK.start(), PAGE_FAULT_STACK.end()),
+        ]
+    }
+    .iter()
+    .any(|&(start, end)| start <= address && address < end)
+}
+
+// Name of the stack whose guard page contains the address, if any
+pub fn overflowed_stack(address: VirtAddr) -> Option<&'static str> {
+    let page = Page::containing_address(address);
+    guard_pages()
+        .into_iter()
+        .find(|&(_, guard)| guard == page)
+        .map(|(name, _)| name)
+}
+
+#[test_case]
+fn test_overflowed_stack() {
+    let double_fault_stack = unsafe { VirtAddr::from_ptr(&DOUBLE_FAULT_STACK) };
+
+    assert_eq!(overflowed_stack(double_fault_stack + 8u64), Some("double fault"));
+    assert_eq!(overflowed_stack(double_fault_stack + PAGE_SIZE), None);
+    assert_eq!(overflowed_stack(VirtAddr::new(KERNEL_STACK_START + 4095)), Some("kernel"));
+}
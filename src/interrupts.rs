@@ -1,15 +1,22 @@
 use crate::{
-    display::get_current_text_color, events::add_event, game::Event, gdt, println,
-    set_text_color,
+    apic,
+    crash::{self, ErrorCode},
+    display::{self, get_current_text_color},
+    error,
+    events::try_add_event,
+    game::Event,
+    gdt, info, println,
+    ps2::{self, MouseDecoder, MouseState},
+    set_text_color, task, tests, time, warn,
 };
 use lazy_static::lazy_static;
 use pic8259::ChainedPics;
-use ps2_mouse::{Mouse, MouseState};
 use spin::{self, Mutex};
 use vga::colors::Color16;
 use x86_64::{
     instructions::{interrupts, port::PortReadOnly},
-    structures::idt::{InterruptDescriptorTable, InterruptStackFrame},
+    registers::control::Cr2,
+    structures::idt::{InterruptDescriptorTable, InterruptStackFrame, PageFaultErrorCode},
 };
 
 pub const PIC_1_OFFSET: u8 = 32;
@@ -24,7 +31,7 @@ pub enum InterruptIndex {
 }
 
 impl InterruptIndex {
-    fn as_u8(self) -> u8 {
+    pub fn as_u8(self) -> u8 {
         self as u8
     }
 
@@ -39,60 +46,151 @@ pub static PICS: spin::Mutex<ChainedPics> =
 lazy_static! {
     static ref IDT: InterruptDescriptorTable = {
         let mut idt = InterruptDescriptorTable::new();
+        idt.divide_error.set_handler_fn(divide_error_handler);
+        idt.debug.set_handler_fn(debug_handler);
+        idt.non_maskable_interrupt.set_handler_fn(non_maskable_interrupt_handler);
         idt.breakpoint.set_handler_fn(breakpoint_handler);
+        idt.overflow.set_handler_fn(overflow_handler);
+        idt.bound_range_exceeded.set_handler_fn(bound_range_exceeded_handler);
+        idt.invalid_opcode.set_handler_fn(invalid_opcode_handler);
+        idt.device_not_available.set_handler_fn(device_not_available_handler);
         unsafe {
             idt.double_fault
                 .set_handler_fn(double_fault_handler)
                 .set_stack_index(gdt::DOUBLE_FAULT_IST_INDEX);
         }
-        idt.page_fault.set_handler_fn(page_fault_handler);
+        idt.invalid_tss.set_handler_fn(invalid_tss_handler);
+        idt.segment_not_present.set_handler_fn(segment_not_present_handler);
+        idt.stack_segment_fault.set_handler_fn(stack_segment_fault_handler);
+        idt.general_protection_fault.set_handler_fn(general_protection_fault_handler);
+        unsafe {
+            idt.page_fault
+                .set_handler_fn(page_fault_handler)
+                .set_stack_index(gdt::PAGE_FAULT_IST_INDEX);
+        }
+        idt.x87_floating_point.set_handler_fn(x87_floating_point_handler);
+        idt.alignment_check.set_handler_fn(alignment_check_handler);
+        idt.machine_check.set_handler_fn(machine_check_handler);
+        idt.simd_floating_point.set_handler_fn(simd_floating_point_handler);
+        idt.virtualization.set_handler_fn(virtualization_handler);
+        idt.vmm_communication_exception.set_handler_fn(vmm_communication_exception_handler);
+        idt.security_exception.set_handler_fn(security_exception_handler);
 
         idt[InterruptIndex::Timer.as_usize()].set_handler_fn(timer_interrupt_handler);
         idt[InterruptIndex::Keyboard.as_usize()].set_handler_fn(keyboard_interrupt_handler);
         idt[InterruptIndex::Mouse.as_usize()].set_handler_fn(mouse_interrupt_handler);
+        idt[apic::SPURIOUS_VECTOR as usize].set_handler_fn(spurious_interrupt_handler);
         idt
     };
-    static ref MOUSE: Mutex<Mouse> = Mutex::new(Mouse::new());
 }
 
+static MOUSE: Mutex<MouseDecoder> = Mutex::new(MouseDecoder::new());
+
 pub fn init_idt() {
     IDT.load();
-    init_mouse();
+    init_ps2();
 }
 
-fn init_mouse() {
-    MOUSE.lock().init().unwrap();
-    MOUSE.lock().set_on_complete(on_complete);
+// Stays on the 8259 PIC if the APIC can't be used
+pub fn init_apic() {
+    match apic::init() {
+        Ok(()) => info!("Using the local APIC and I/O APIC for interrupts"),
+        Err(err) => warn!("Using the 8259 PIC for interrupts, no APIC: {}", err),
+    }
+}
+
+pub fn end_of_interrupt(index: InterruptIndex) {
+    match apic::local_apic() {
+        Some(local_apic) => local_apic.end_of_interrupt(),
+        None => unsafe { PICS.lock().notify_end_of_interrupt(index.as_u8()) },
+    }
+}
+
+// The game falls back to keyboard only when there is no mouse
+fn init_ps2() {
+    if let Err(err) = ps2::init() {
+        error!("PS/2 controller failed to initialize: {}", err);
+    }
 }
 
 // This will be fired when a packet is finished being processed.
+// The packets pile up while the engine searches, they are dropped once the queue is full
+// since the cursor task keeps drawing the cursor meanwhile
 fn on_complete(mouse_state: MouseState) {
-    // println!("Origin: {:?}", mouse_state);
     interrupts::without_interrupts(|| {
-        add_event(Event::MouseInput(mouse_state));
+        task::mouse::add_mouse_state(mouse_state);
+        try_add_event(Event::MouseInput(mouse_state));
     });
 }
 
+// Defines a handler for an exception that can't be recovered from, showing the crash screen
+macro_rules! crash_handler {
+    ($handler:ident, $name:expr) => {
+        extern "x86-interrupt" fn $handler(stack_frame: InterruptStackFrame) {
+            crash::exception($name, &stack_frame, None)
+        }
+    };
+    ($handler:ident, $name:expr, $error_code:path) => {
+        extern "x86-interrupt" fn $handler(stack_frame: InterruptStackFrame, error_code: u64) {
+            crash::exception($name, &stack_frame, Some($error_code(error_code)))
+        }
+    };
+}
+
+crash_handler!(divide_error_handler, "DIVIDE ERROR");
+crash_handler!(non_maskable_interrupt_handler, "NON MASKABLE INTERRUPT");
+crash_handler!(overflow_handler, "OVERFLOW");
+crash_handler!(bound_range_exceeded_handler, "BOUND RANGE EXCEEDED");
+crash_handler!(invalid_opcode_handler, "INVALID OPCODE");
+crash_handler!(device_not_available_handler, "DEVICE NOT AVAILABLE");
+crash_handler!(x87_floating_point_handler, "X87 FLOATING POINT");
+crash_handler!(simd_floating_point_handler, "SIMD FLOATING POINT");
+crash_handler!(virtualization_handler, "VIRTUALIZATION");
+crash_handler!(invalid_tss_handler, "INVALID TSS", ErrorCode::Selector);
+crash_handler!(segment_not_present_handler, "SEGMENT NOT PRESENT", ErrorCode::Selector);
+crash_handler!(stack_segment_fault_handler, "STACK SEGMENT FAULT", ErrorCode::Selector);
+crash_handler!(general_protection_fault_handler, "GENERAL PROTECTION FAULT", ErrorCode::Selector);
+crash_handler!(alignment_check_handler, "ALIGNMENT CHECK", ErrorCode::Raw);
+crash_handler!(vmm_communication_exception_handler, "VMM COMMUNICATION EXCEPTION", ErrorCode::Raw);
+crash_handler!(security_exception_handler, "SECURITY EXCEPTION", ErrorCode::Raw);
+
+extern "x86-interrupt" fn debug_handler(stack_frame: InterruptStackFrame) {
+    warn!("EXCEPTION: DEBUG\n{:#?}", stack_frame);
+}
+
 extern "x86-interrupt" fn breakpoint_handler(stack_frame: InterruptStackFrame) {
-    let color = get_current_text_color();
-    set_text_color!(Color16::Yellow, Color16::Black);
-    println!("EXCEPTION: BREAKPOINT\n{:#?}", stack_frame);
-    set_text_color!(color);
+    warn!("EXCEPTION: BREAKPOINT\n{:#?}", stack_frame);
+
+    // Printing would switch away from graphics mode, and the game would need a redraw
+    if !display::is_graphics_mode() {
+        let color = get_current_text_color();
+        set_text_color!(Color16::Yellow, Color16::Black);
+        println!("EXCEPTION: BREAKPOINT\n{:#?}", stack_frame);
+        set_text_color!(color);
+    }
 }
 
 extern "x86-interrupt" fn double_fault_handler(
     stack_frame: InterruptStackFrame,
-    _error_code: u64,
+    error_code: u64,
 ) -> ! {
-    set_text_color!(Color16::Yellow, Color16::Black);
-    panic!("EXCEPTION: DOUBLE FAULT\n{:#?}", stack_frame);
+    // In case the page fault handler itself overflowed
+    if let Some(stack) = gdt::overflowed_stack(Cr2::read()) {
+        crash::stack_overflow(stack, &stack_frame);
+    }
+
+    crash::exception("DOUBLE FAULT", &stack_frame, Some(ErrorCode::Raw(error_code)))
+}
+
+extern "x86-interrupt" fn machine_check_handler(stack_frame: InterruptStackFrame) -> ! {
+    crash::exception("MACHINE CHECK", &stack_frame, None)
 }
 
 extern "x86-interrupt" fn timer_interrupt_handler(_stack_frame: InterruptStackFrame) {
-    unsafe {
-        PICS.lock()
-            .notify_end_of_interrupt(InterruptIndex::Timer.as_u8());
-    }
+    time::tick();
+    tests::check_timeout();
+
+    end_of_interrupt(InterruptIndex::Timer);
 }
 
 extern "x86-interrupt" fn keyboard_interrupt_handler(_stack_frame: InterruptStackFrame) {
@@ -119,46 +217,39 @@ extern "x86-interrupt" fn keyboard_interrupt_handler(_stack_frame: InterruptStac
         }
     }
 
+    // Dropped if the queue is full, waiting here would never let the game empty it
     if let Some(event) = event {
-        interrupts::without_interrupts(|| {
-            add_event(event);
-        });
+        try_add_event(event);
     }
 
-    unsafe {
-        PICS.lock()
-            .notify_end_of_interrupt(InterruptIndex::Keyboard.as_u8());
-    }
+    end_of_interrupt(InterruptIndex::Keyboard);
 }
 
-use x86_64::structures::idt::PageFaultErrorCode;
-
 extern "x86-interrupt" fn page_fault_handler(
     stack_frame: InterruptStackFrame,
     error_code: PageFaultErrorCode,
 ) {
-    use x86_64::registers::control::Cr2;
-
-    set_text_color!(Color16::Yellow, Color16::Black);
+    if let Some(stack) = gdt::overflowed_stack(Cr2::read()) {
+        crash::stack_overflow(stack, &stack_frame);
+    }
 
-    println!("EXCEPTION: PAGE FAULT");
-    println!("Accessed Address: {:?}", Cr2::read());
-    println!("Error Code: {:?}", error_code);
-    println!("{:#?}", stack_frame);
-    loop {}
+    crash::exception("PAGE FAULT", &stack_frame, Some(ErrorCode::PageFault(error_code)))
 }
 
+// Sent by the local APIC for interrupts that disappeared before being handled, needs no EOI
+extern "x86-interrupt" fn spurious_interrupt_handler(_stack_frame: InterruptStackFrame) {}
+
 // An example interrupt based on https://os.phil-opp.com/hardware-interrupts/. The ps2 mouse is configured to fire
 // interrupts at PIC offset 12.
 extern "x86-interrupt" fn mouse_interrupt_handler(_stack_frame: InterruptStackFrame) {
     let mut port = PortReadOnly::new(0x60);
-    let packet = unsafe { port.read() };
-    MOUSE.lock().process_packet(packet);
-
-    unsafe {
-        PICS.lock()
-            .notify_end_of_interrupt(InterruptIndex::Mouse as u8);
+    let byte = unsafe { port.read() };
+    let state = MOUSE.lock().add_byte(byte);
+    if let Some(state) = state {
+        on_complete(state);
     }
+
+    end_of_interrupt(InterruptIndex::Mouse);
 }
 
 #[test_case]
@@ -4,20 +4,40 @@
 #![feature(abi_x86_interrupt)]
 #![test_runner(crate::tests::test_runner)]
 #![reexport_test_harness_main = "test_main"]
-#![feature(default_alloc_error_handler)]
+#![feature(alloc_error_handler)]
+#![feature(allocator_api)]
 
 extern crate alloc;
 
+pub mod acpi;
 pub mod allocator;
+pub mod apic;
+pub mod backtrace;
+pub mod crash;
 pub mod display;
 pub mod entities;
 pub mod events;
+pub mod fs;
 pub mod game;
 pub mod gdt;
 pub mod interrupts;
+pub mod logger;
 pub mod memory;
+pub mod notation;
+pub mod pci;
+pub mod pgn;
+pub mod power;
+pub mod ps2;
 pub mod queue;
+pub mod rtc;
+pub mod savegame;
+pub mod serial;
+pub mod settings;
+pub mod speaker;
+pub mod storage;
+pub mod task;
 pub mod tests;
+pub mod time;
 
 #[cfg(test)]
 use bootloader::entry_point;
@@ -34,8 +54,20 @@ fn panic(info: &core::panic::PanicInfo) -> ! {
 }
 
 #[cfg(test)]
-fn test_kernel_main(_boot_info: &'static bootloader::BootInfo) -> ! {
+fn test_kernel_main(boot_info: &'static bootloader::BootInfo) -> ! {
     init();
+
+    // Some tests need the heap
+    let phys_mem_offset = x86_64::VirtAddr::new(boot_info.physical_memory_offset);
+    unsafe { memory::init(phys_mem_offset, &boot_info.memory_map) };
+    gdt::init_guard_pages();
+    allocator::init_heap().expect("heap initialization failed");
+    acpi::init();
+    interrupts::init_apic();
+    pci::init();
+    storage::init();
+    fs::init();
+
     test_main();
     loop {}
 }
@@ -44,5 +76,6 @@ pub fn init() {
     gdt::init();
     interrupts::init_idt();
     unsafe { interrupts::PICS.lock().initialize() };
+    time::init();
     x86_64::instructions::interrupts::enable();
 }
@@ -0,0 +1,66 @@
+use core::{
+    fmt,
+    sync::atomic::{AtomicU8, Ordering},
+};
+
+use crate::{serial, time};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[repr(u8)]
+pub enum Level {
+    Info,
+    Warn,
+    Error,
+}
+
+static MIN_LEVEL: AtomicU8 = AtomicU8::new(Level::Info as u8);
+
+// Everything logged goes to the serial port, so it stays visible when in graphics mode
+#[macro_export]
+macro_rules! log {
+    ($level:expr, $($arg:tt)*) => ($crate::logger::_log($level, format_args!($($arg)*)));
+}
+
+#[macro_export]
+macro_rules! info {
+    ($($arg:tt)*) => ($crate::log!($crate::logger::Level::Info, $($arg)*));
+}
+
+#[macro_export]
+macro_rules! warn {
+    ($($arg:tt)*) => ($crate::log!($crate::logger::Level::Warn, $($arg)*));
+}
+
+#[macro_export]
+macro_rules! error {
+    ($($arg:tt)*) => ($crate::log!($crate::logger::Level::Error, $($arg)*));
+}
+
+impl Level {
+    pub fn name(self) -> &'static str {
+        match self {
+            Level::Info => "INFO",
+            Level::Warn => "WARN",
+            Level::Error => "ERROR",
+        }
+    }
+}
+
+pub fn set_min_level(level: Level) {
+    MIN_LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+pub fn _log(level: Level, args: fmt::Arguments) {
+    if (level as u8) < MIN_LEVEL.load(Ordering::Relaxed) {
+        return;
+    }
+
+    let uptime = time::uptime();
+    serial::_print(format_args!(
+        "[{:>5}.{:03}] {:<5} {}\n",
+        uptime.as_secs(),
+        uptime.subsec_millis(),
+        level.name(),
+        args
+    ));
+}
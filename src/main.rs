@@ -3,21 +3,23 @@
 #![feature(custom_test_frameworks)]
 #![test_runner(bmc_os::tests::test_runner)]
 #![reexport_test_harness_main = "test_main"]
-#![feature(default_alloc_error_handler)]
 
 extern crate alloc;
 
 use bmc_os::{
-    allocator,
-    events::{add_event, next_event},
+    acpi, allocator,
+    events::{self, post_event, try_add_event},
+    fs,
     game::{Event, Game},
-    memory::{self, BootInfoFrameAllocator},
+    gdt, info, interrupts, memory, pci, rtc, storage,
+    task::{executor, mouse},
+    time,
 };
 use bootloader::{entry_point, BootInfo};
 
 use x86_64::VirtAddr;
 
-use core::panic::PanicInfo;
+use core::{panic::PanicInfo, time::Duration};
 
 // Should never be called, but just to satisty compiler
 #[cfg(test)]
@@ -31,12 +33,7 @@ fn panic(info: &PanicInfo) -> ! {
 #[cfg(not(test))]
 #[panic_handler]
 fn panic(info: &PanicInfo) -> ! {
-    use bmc_os::{println, set_text_color};
-    use vga::colors::Color16;
-
-    set_text_color!(Color16::Red, Color16::Black);
-    println!("{}", info);
-    loop {}
+    bmc_os::crash::panic(info)
 }
 entry_point!(kernel_main);
 
@@ -44,18 +41,55 @@ fn kernel_main(boot_info: &'static BootInfo) -> ! {
     bmc_os::init();
 
     let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
-    let mut mapper = unsafe { memory::init(phys_mem_offset) };
-    let mut frame_allocator = unsafe { BootInfoFrameAllocator::init(&boot_info.memory_map) };
+    unsafe { memory::init(phys_mem_offset, &boot_info.memory_map) };
+    gdt::init_guard_pages();
+
+    allocator::init_heap().expect("heap initialization failed");
+    acpi::init();
+    interrupts::init_apic();
+    pci::init();
+    storage::init();
+    fs::init();
+
+    let (used_frames, free_frames) = {
+        let frame_allocator = memory::frame_allocator();
+        (frame_allocator.used_frames(), frame_allocator.free_frames())
+    };
+    info!(
+        "Physical memory: {} frames used, {} free, heap limit {} MiB",
+        used_frames,
+        free_frames,
+        allocator::heap_limit() / 1024 / 1024
+    );
+
+    #[cfg(test)]
+    test_main();
+
+    info!("Booted at {}", rtc::now());
 
-    allocator::init_heap(&mut mapper, &mut frame_allocator).expect("heap initialization failed");
+    let game = Game::new();
 
-    let mut game = Game::new();
+    post_event(Event::ReturnToMenu);
 
-    add_event(Event::ReturnToMenu);
+    executor::spawn(game_loop(game));
+    executor::spawn(mouse::cursor_task());
+    executor::spawn(tick_events());
+    executor::run();
+}
 
+async fn game_loop(mut game: Game<'static>) {
     loop {
-        let event = next_event();
+        let event = events::next().await;
         game.handle_event(&event);
         game.draw();
     }
 }
+
+async fn tick_events() {
+    loop {
+        time::sleep_async(Duration::from_secs(1)).await;
+        // Polled during a search too, when the game isn't emptying the queue. A missed tick
+        // only delays the clock by a second
+        try_add_event(Event::Tick);
+    }
+}
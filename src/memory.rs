@@ -1,9 +1,21 @@
+use core::slice;
+
 use bootloader::bootinfo::{MemoryMap, MemoryRegionType};
+use spin::{Mutex, MutexGuard, Once};
 use x86_64::{
-    structures::paging::{FrameAllocator, OffsetPageTable, PageTable, PhysFrame, Size4KiB},
+    structures::paging::{
+        mapper::UnmapError, FrameAllocator, FrameDeallocator, Mapper, OffsetPageTable, Page,
+        PageTable, PageTableFlags, PhysFrame, Size4KiB, Translate,
+    },
     PhysAddr, VirtAddr,
 };
 
+use crate::warn;
+
+static MAPPER: Once<Mutex<OffsetPageTable<'static>>> = Once::new();
+static FRAME_ALLOCATOR: Once<Mutex<BitmapFrameAllocator>> = Once::new();
+static PHYSICAL_MEMORY_OFFSET: Once<VirtAddr> = Once::new();
+
 unsafe fn active_level_4_table(physical_memory_offset: VirtAddr) -> &'static mut PageTable {
     use x86_64::registers::control::Cr3;
 
@@ -16,40 +28,238 @@ unsafe fn active_level_4_table(physical_memory_offset: VirtAddr) -> &'static mut
     &mut *page_table_ptr
 }
 
-pub unsafe fn init(physical_memory_offset: VirtAddr) -> OffsetPageTable<'static> {
+// The whole physical memory must be mapped at the offset, and this must only be called once
+pub unsafe fn init(physical_memory_offset: VirtAddr, memory_map: &'static MemoryMap) {
     let level_4_table = active_level_4_table(physical_memory_offset);
-    OffsetPageTable::new(level_4_table, physical_memory_offset)
+    let mapper = OffsetPageTable::new(level_4_table, physical_memory_offset);
+    let frame_allocator = BitmapFrameAllocator::init(memory_map, physical_memory_offset);
+
+    PHYSICAL_MEMORY_OFFSET.call_once(|| physical_memory_offset);
+    MAPPER.call_once(|| Mutex::new(mapper));
+    FRAME_ALLOCATOR.call_once(|| Mutex::new(frame_allocator));
+}
+
+// The heap grows through these, so nothing should allocate while holding them
+pub fn mapper() -> MutexGuard<'static, OffsetPageTable<'static>> {
+    MAPPER.get().expect("memory not initialized").lock()
+}
+
+pub fn physical_to_virtual(addr: PhysAddr) -> VirtAddr {
+    *PHYSICAL_MEMORY_OFFSET.get().expect("memory not initialized") + addr.as_u64()
+}
+
+// For device registers and firmware tables that the bootloader might not have mapped,
+// the missing pages are mapped uncached at their usual place in the physical memory mapping
+pub fn map_physical_region(addr: PhysAddr, size: usize) -> VirtAddr {
+    let start = physical_to_virtual(addr);
+    let end = start + size.max(1) - 1u64;
+    let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::NO_CACHE;
+
+    let mut mapper = mapper();
+    let mut frame_allocator = frame_allocator();
+
+    let first: Page = Page::containing_address(start);
+    let last = Page::containing_address(end);
+    for page in Page::range_inclusive(first, last) {
+        if mapper.translate_addr(page.start_address()).is_some() {
+            continue;
+        }
+
+        let offset = *PHYSICAL_MEMORY_OFFSET.get().unwrap();
+        let frame = PhysFrame::containing_address(PhysAddr::new(page.start_address() - offset));
+        match unsafe { mapper.map_to(page, frame, flags, &mut *frame_allocator) } {
+            Ok(flush) => flush.flush(),
+            Err(err) => warn!("Could not map physical frame {:?}: {:?}", frame, err),
+        }
+    }
+
+    start
+}
+
+// Any access to the page will fault, the frame behind it is not reused
+pub fn unmap_guard_page(page: Page) {
+    let res = mapper().unmap(page);
+    match res {
+        Ok((_, flush)) => flush.flush(),
+        Err(UnmapError::PageNotMapped) => {}
+        Err(err) => warn!("Could not unmap guard page {:?}: {:?}", page, err),
+    }
 }
 
-pub struct BootInfoFrameAllocator {
-    memory_map: &'static MemoryMap,
+pub fn frame_allocator() -> MutexGuard<'static, BitmapFrameAllocator> {
+    FRAME_ALLOCATOR.get().expect("memory not initialized").lock()
+}
+
+const FRAME_SIZE: u64 = 4096;
+const BITS_PER_WORD: usize = 64;
+
+// One bit per physical frame, set when the frame is used or not usable
+pub struct BitmapFrameAllocator {
+    bitmap: &'static mut [u64],
+    usable_frames: usize,
+    used_frames: usize,
+    // Word where the last free frame was found, to avoid rescanning the start
     next: usize,
 }
 
-impl BootInfoFrameAllocator {
-    pub unsafe fn init(memory_map: &'static MemoryMap) -> Self {
-        BootInfoFrameAllocator {
-            memory_map,
+impl BitmapFrameAllocator {
+    // The bitmap is stored in the first usable region big enough to hold it,
+    // so the memory map must be valid and all usable frames unused
+    pub unsafe fn init(memory_map: &'static MemoryMap, physical_memory_offset: VirtAddr) -> Self {
+        let usable_regions = || {
+            memory_map
+                .iter()
+                .filter(|r| r.region_type == MemoryRegionType::Usable)
+        };
+
+        let frame_count = usable_regions()
+            .map(|r| r.range.end_frame_number)
+            .max()
+            .unwrap_or(0) as usize;
+        let words = (frame_count + BITS_PER_WORD - 1) / BITS_PER_WORD;
+        let bitmap_frames = (words as u64 * 8 + FRAME_SIZE - 1) / FRAME_SIZE;
+
+        let bitmap_start = usable_regions()
+            .find(|r| r.range.end_frame_number - r.range.start_frame_number >= bitmap_frames)
+            .expect("no usable memory region can hold the frame bitmap")
+            .range
+            .start_frame_number;
+
+        let bitmap_addr = physical_memory_offset + bitmap_start * FRAME_SIZE;
+        let bitmap = slice::from_raw_parts_mut(bitmap_addr.as_mut_ptr::<u64>(), words);
+
+        // Everything starts as used, then the usable frames are freed
+        bitmap.fill(u64::MAX);
+
+        let mut allocator = BitmapFrameAllocator {
+            bitmap,
+            usable_frames: 0,
+            used_frames: 0,
             next: 0,
+        };
+
+        for region in usable_regions() {
+            for frame in region.range.start_frame_number..region.range.end_frame_number {
+                allocator.set_free(frame as usize);
+                allocator.usable_frames += 1;
+            }
+        }
+
+        for frame in bitmap_start..bitmap_start + bitmap_frames {
+            allocator.set_used(frame as usize);
+            allocator.used_frames += 1;
+        }
+
+        allocator
+    }
+
+    pub fn usable_frames(&self) -> usize {
+        self.usable_frames
+    }
+
+    pub fn used_frames(&self) -> usize {
+        self.used_frames
+    }
+
+    pub fn free_frames(&self) -> usize {
+        self.usable_frames - self.used_frames
+    }
+
+    // Devices reading and writing memory on their own need physically contiguous buffers,
+    // the frames are accessed through the physical memory mapping
+    pub fn allocate_contiguous(&mut self, count: usize) -> Option<PhysFrame> {
+        let mut run = 0;
+        for index in 0..self.bitmap.len() * BITS_PER_WORD {
+            match self.is_used(index) {
+                true => run = 0,
+                false => run += 1,
+            }
+
+            if run == count {
+                let first = index + 1 - count;
+                for frame in first..=index {
+                    self.set_used(frame);
+                }
+                self.used_frames += count;
+
+                let addr = PhysAddr::new(first as u64 * FRAME_SIZE);
+                return Some(PhysFrame::containing_address(addr));
+            }
         }
+
+        None
+    }
+
+    fn is_used(&self, index: usize) -> bool {
+        self.bitmap[index / BITS_PER_WORD] & (1 << (index % BITS_PER_WORD)) != 0
+    }
+
+    fn set_used(&mut self, index: usize) {
+        self.bitmap[index / BITS_PER_WORD] |= 1 << (index % BITS_PER_WORD);
     }
-}
 
-impl BootInfoFrameAllocator {
-    fn usable_frames(&self) -> impl Iterator<Item = PhysFrame> {
-        self.memory_map
-            .iter()
-            .filter(|r| r.region_type == MemoryRegionType::Usable)
-            .map(|r| r.range.start_addr()..r.range.end_addr())
-            .flat_map(|r| r.step_by(4096))
-            .map(|addr| PhysFrame::containing_address(PhysAddr::new(addr)))
+    fn set_free(&mut self, index: usize) {
+        self.bitmap[index / BITS_PER_WORD] &= !(1 << (index % BITS_PER_WORD));
     }
 }
 
-unsafe impl FrameAllocator<Size4KiB> for BootInfoFrameAllocator {
+unsafe impl FrameAllocator<Size4KiB> for BitmapFrameAllocator {
     fn allocate_frame(&mut self) -> Option<PhysFrame> {
-        let frame = self.usable_frames().nth(self.next);
-        self.next += 1;
-        frame
+        let words = self.bitmap.len();
+
+        for offset in 0..words {
+            let word = (self.next + offset) % words;
+            if self.bitmap[word] == u64::MAX {
+                continue;
+            }
+
+            let index = word * BITS_PER_WORD + self.bitmap[word].trailing_ones() as usize;
+            self.set_used(index);
+            self.used_frames += 1;
+            self.next = word;
+
+            let addr = PhysAddr::new(index as u64 * FRAME_SIZE);
+            return Some(PhysFrame::containing_address(addr));
+        }
+
+        None
+    }
+}
+
+impl FrameDeallocator<Size4KiB> for BitmapFrameAllocator {
+    // The frame must have been returned by `allocate_frame` and not be in use anymore
+    unsafe fn deallocate_frame(&mut self, frame: PhysFrame) {
+        let index = (frame.start_address().as_u64() / FRAME_SIZE) as usize;
+        assert!(
+            index < self.bitmap.len() * BITS_PER_WORD && self.is_used(index),
+            "deallocating a frame that is not allocated: {:?}",
+            frame
+        );
+
+        self.set_free(index);
+        self.used_frames -= 1;
+        self.next = self.next.min(index / BITS_PER_WORD);
+    }
+}
+
+#[test_case]
+fn test_deallocate_frames() {
+    let mut allocator = frame_allocator();
+    let free = allocator.free_frames();
+
+    let first = allocator.allocate_frame().unwrap();
+    let second = allocator.allocate_frame().unwrap();
+    assert_ne!(first, second);
+    assert_eq!(allocator.free_frames(), free - 2);
+
+    // The lowest free frame is handed out first, so the freed one comes back
+    unsafe { allocator.deallocate_frame(first) };
+    assert_eq!(allocator.free_frames(), free - 1);
+    assert_eq!(allocator.allocate_frame(), Some(first));
+
+    unsafe {
+        allocator.deallocate_frame(first);
+        allocator.deallocate_frame(second);
     }
+    assert_eq!(allocator.free_frames(), free);
 }
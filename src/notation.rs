@@ -0,0 +1,345 @@
+use alloc::string::String;
+
+use cozy_chess::{Board, File, Move, Piece, Rank, Square};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NotationError {
+    Invalid,
+    Illegal,
+    Ambiguous,
+}
+
+impl NotationError {
+    pub fn message(self) -> &'static str {
+        match self {
+            NotationError::Invalid => "Invalid",
+            NotationError::Illegal => "Illegal",
+            NotationError::Ambiguous => "Ambiguous",
+        }
+    }
+}
+
+// Parses a move written in either SAN (Nf3, exd5, e8=Q, O-O) or UCI (g1f3, e7e8q)
+pub fn parse_move(board: &Board, text: &str) -> Result<Move, NotationError> {
+    let text = text.trim();
+
+    if let Some(mv) = parse_uci(board, text) {
+        return match board.is_legal(mv) {
+            true => Ok(mv),
+            false => Err(NotationError::Illegal),
+        };
+    }
+
+    parse_san(board, text)
+}
+
+fn parse_file(c: u8) -> Option<File> {
+    match c {
+        b'a'..=b'h' => Some(File::index((c - b'a') as usize)),
+        _ => None,
+    }
+}
+
+fn parse_rank(c: u8) -> Option<Rank> {
+    match c {
+        b'1'..=b'8' => Some(Rank::index((c - b'1') as usize)),
+        _ => None,
+    }
+}
+
+fn parse_square(file: u8, rank: u8) -> Option<Square> {
+    Some(Square::new(parse_file(file)?, parse_rank(rank)?))
+}
+
+fn parse_piece(c: u8) -> Option<Piece> {
+    match c.to_ascii_uppercase() {
+        b'N' => Some(Piece::Knight),
+        b'B' => Some(Piece::Bishop),
+        b'R' => Some(Piece::Rook),
+        b'Q' => Some(Piece::Queen),
+        b'K' => Some(Piece::King),
+        _ => None,
+    }
+}
+
+fn piece_char(piece: Piece) -> char {
+    match piece {
+        Piece::Pawn => 'P',
+        Piece::Knight => 'N',
+        Piece::Bishop => 'B',
+        Piece::Rook => 'R',
+        Piece::Queen => 'Q',
+        Piece::King => 'K',
+    }
+}
+
+fn file_char(file: File) -> char {
+    (b'a' + file as u8) as char
+}
+
+fn rank_char(rank: Rank) -> char {
+    (b'1' + rank as u8) as char
+}
+
+// Writes a legal move in SAN, the board being the position before the move
+pub fn to_san(board: &Board, mv: Move) -> String {
+    let mut san = String::new();
+    let color = board.side_to_move();
+    let piece = board.piece_on(mv.from).unwrap_or(Piece::Pawn);
+
+    if piece == Piece::King && board.color_on(mv.to) == Some(color) {
+        san.push_str(match mv.to.file() > mv.from.file() {
+            true => "O-O",
+            false => "O-O-O",
+        });
+    } else {
+        // En passant is the only capture of a pawn changing file onto an empty square
+        let capture = board.color_on(mv.to) == Some(!color)
+            || (piece == Piece::Pawn && mv.from.file() != mv.to.file());
+
+        if piece == Piece::Pawn {
+            if capture {
+                san.push(file_char(mv.from.file()));
+            }
+        } else {
+            san.push(piece_char(piece));
+            push_disambiguation(&mut san, board, mv, piece);
+        }
+
+        if capture {
+            san.push('x');
+        }
+        san.push(file_char(mv.to.file()));
+        san.push(rank_char(mv.to.rank()));
+
+        if let Some(promotion) = mv.promotion {
+            san.push('=');
+            san.push(piece_char(promotion));
+        }
+    }
+
+    let mut after = board.clone();
+    after.play_unchecked(mv);
+    if !after.checkers().is_empty() {
+        let mut mate = true;
+        after.generate_moves(|_| {
+            mate = false;
+            true
+        });
+        san.push(if mate { '#' } else { '+' });
+    }
+
+    san
+}
+
+// Adds the file, rank or both of the origin when another piece of the same kind
+// can move to the same square
+fn push_disambiguation(san: &mut String, board: &Board, mv: Move, piece: Piece) {
+    let mut ambiguous = false;
+    let mut same_file = false;
+    let mut same_rank = false;
+    board.generate_moves(|moves| {
+        if moves.piece != piece {
+            return false;
+        }
+        for other in moves {
+            if other.to == mv.to && other.from != mv.from {
+                ambiguous = true;
+                same_file |= other.from.file() == mv.from.file();
+                same_rank |= other.from.rank() == mv.from.rank();
+            }
+        }
+        false
+    });
+
+    if !ambiguous {
+        return;
+    }
+    if !same_file {
+        san.push(file_char(mv.from.file()));
+    } else if !same_rank {
+        san.push(rank_char(mv.from.rank()));
+    } else {
+        san.push(file_char(mv.from.file()));
+        san.push(rank_char(mv.from.rank()));
+    }
+}
+
+fn parse_uci(board: &Board, text: &str) -> Option<Move> {
+    let bytes = text.as_bytes();
+    if bytes.len() != 4 && bytes.len() != 5 {
+        return None;
+    }
+
+    let from = parse_square(bytes[0], bytes[1])?;
+    let mut to = parse_square(bytes[2], bytes[3])?;
+    let promotion = match bytes.get(4) {
+        Some(&c) => Some(parse_piece(c)?),
+        None => None,
+    };
+
+    // cozy-chess represents castling as the king capturing its own rook
+    let color = board.side_to_move();
+    let is_king = board.piece_on(from) == Some(Piece::King) && board.color_on(from) == Some(color);
+    if is_king && from.file() == File::E && from.rank() == to.rank() {
+        let rights = board.castle_rights(color);
+        let rook_file = match to.file() {
+            File::G => rights.short,
+            File::C => rights.long,
+            _ => None,
+        };
+        if let Some(file) = rook_file {
+            to = Square::new(file, to.rank());
+        }
+    }
+
+    Some(Move {
+        from,
+        to,
+        promotion,
+    })
+}
+
+fn parse_castle(board: &Board, text: &str) -> Option<Result<Move, NotationError>> {
+    let color = board.side_to_move();
+    let rights = board.castle_rights(color);
+    let rook_file = match text {
+        "O-O" | "0-0" => rights.short,
+        "O-O-O" | "0-0-0" => rights.long,
+        _ => return None,
+    };
+
+    let mv = rook_file.map(|file| Move {
+        from: board.king(color),
+        to: Square::new(file, Rank::First.relative_to(color)),
+        promotion: None,
+    });
+
+    Some(match mv {
+        Some(mv) if board.is_legal(mv) => Ok(mv),
+        _ => Err(NotationError::Illegal),
+    })
+}
+
+fn parse_san(board: &Board, text: &str) -> Result<Move, NotationError> {
+    let text = text.trim_end_matches(|c| matches!(c, '+' | '#' | '!' | '?'));
+
+    if let Some(res) = parse_castle(board, text) {
+        return res;
+    }
+
+    let mut bytes = text.as_bytes();
+
+    let piece = match bytes.first() {
+        Some(&c) if c.is_ascii_uppercase() => {
+            bytes = &bytes[1..];
+            parse_piece(c).ok_or(NotationError::Invalid)?
+        }
+        Some(_) => Piece::Pawn,
+        None => return Err(NotationError::Invalid),
+    };
+
+    // Promotion, either e8=Q or e8Q
+    let mut promotion = None;
+    if let Some((&last, rest)) = bytes.split_last() {
+        if let Some(promoted) = parse_piece(last).filter(|_| last.is_ascii_uppercase()) {
+            promotion = Some(promoted);
+            bytes = rest.strip_suffix(b"=").unwrap_or(rest);
+        }
+    }
+
+    if bytes.len() < 2 {
+        return Err(NotationError::Invalid);
+    }
+
+    let (rest, dest) = bytes.split_at(bytes.len() - 2);
+    let to = parse_square(dest[0], dest[1]).ok_or(NotationError::Invalid)?;
+
+    let mut from_file = None;
+    let mut from_rank = None;
+    for &c in rest {
+        match c {
+            b'x' | b':' => {}
+            b'a'..=b'h' => from_file = parse_file(c),
+            b'1'..=b'8' => from_rank = parse_rank(c),
+            _ => return Err(NotationError::Invalid),
+        }
+    }
+
+    let mut found = None;
+    let mut ambiguous = false;
+    board.generate_moves(|moves| {
+        if moves.piece != piece {
+            return false;
+        }
+        for mv in moves {
+            let matches = mv.to == to
+                && mv.promotion == promotion
+                && from_file.map_or(true, |f| mv.from.file() == f)
+                && from_rank.map_or(true, |r| mv.from.rank() == r)
+                // The king "capturing" its own rook is castling, written with O-O
+                && board.color_on(mv.to) != Some(board.side_to_move());
+
+            if matches {
+                ambiguous |= found.is_some();
+                found = Some(mv);
+            }
+        }
+        false
+    });
+
+    match (found, ambiguous) {
+        (Some(_), true) => Err(NotationError::Ambiguous),
+        (Some(mv), false) => Ok(mv),
+        (None, _) => Err(NotationError::Illegal),
+    }
+}
+
+#[test_case]
+fn test_parse_start_position() {
+    let board = Board::default();
+    let knight = Move {
+        from: Square::G1,
+        to: Square::F3,
+        promotion: None,
+    };
+
+    assert_eq!(parse_move(&board, "Nf3"), Ok(knight));
+    assert_eq!(parse_move(&board, "g1f3"), Ok(knight));
+    assert_eq!(parse_move(&board, "e5"), Err(NotationError::Illegal));
+    assert_eq!(parse_move(&board, "Zz9"), Err(NotationError::Invalid));
+}
+
+#[test_case]
+fn test_parse_castling_and_disambiguation() {
+    let board: Board = "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1".parse().unwrap();
+    let short_castle = Move {
+        from: Square::E1,
+        to: Square::H1,
+        promotion: None,
+    };
+
+    assert_eq!(parse_move(&board, "O-O"), Ok(short_castle));
+    assert_eq!(parse_move(&board, "e1g1"), Ok(short_castle));
+
+    let board: Board = "4k3/8/8/8/8/8/4K3/R6R w - - 0 1".parse().unwrap();
+    assert_eq!(parse_move(&board, "Rd1"), Err(NotationError::Ambiguous));
+    assert_eq!(parse_move(&board, "Rad1").map(|mv| mv.from), Ok(Square::A1));
+}
+
+#[test_case]
+fn test_to_san() {
+    let board: Board = "r3k2r/8/8/8/8/8/4K3/R6R w kq - 0 1".parse().unwrap();
+    let mv = |text| parse_move(&board, text).unwrap();
+
+    assert_eq!(to_san(&board, mv("a1d1")), "Rad1");
+    assert_eq!(to_san(&board, mv("h1h8")), "Rxh8+");
+
+    let board: Board = "4k3/8/8/8/8/8/8/R3K2R w KQ - 0 1".parse().unwrap();
+    let castle = parse_move(&board, "O-O-O").unwrap();
+    assert_eq!(to_san(&board, castle), "O-O-O");
+
+    let board: Board = "7k/P7/6K1/8/8/8/8/8 w - - 0 1".parse().unwrap();
+    let promotion = parse_move(&board, "a7a8q").unwrap();
+    assert_eq!(to_san(&board, promotion), "a8=Q#");
+}
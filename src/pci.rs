@@ -0,0 +1,248 @@
+use alloc::vec::Vec;
+
+use spin::Once;
+use x86_64::instructions::port::Port;
+
+use crate::info;
+
+// https://wiki.osdev.org/PCI, configuration space access mechanism #1
+const CONFIG_ADDRESS: u16 = 0xCF8;
+const CONFIG_DATA: u16 = 0xCFC;
+const CONFIG_ENABLE: u32 = 1 << 31;
+
+const BUS_COUNT: u16 = 256;
+const DEVICES_PER_BUS: u8 = 32;
+const FUNCTIONS_PER_DEVICE: u8 = 8;
+// Read when there is no function at an address
+const NO_VENDOR: u16 = 0xFFFF;
+
+// Offsets in the configuration space
+const OFFSET_VENDOR: u8 = 0x00;
+const OFFSET_COMMAND: u8 = 0x04;
+const OFFSET_CLASS: u8 = 0x08;
+const OFFSET_HEADER_TYPE: u8 = 0x0C;
+const OFFSET_BARS: u8 = 0x10;
+
+const HEADER_TYPE_MASK: u8 = 0x7F;
+const HEADER_MULTI_FUNCTION: u8 = 0x80;
+// Devices, the other header types are bridges with a different layout
+const HEADER_TYPE_GENERAL: u8 = 0x00;
+const BAR_COUNT: u8 = 6;
+
+const BAR_IO: u32 = 1 << 0;
+const BAR_64_BIT: u32 = 0x2 << 1;
+const BAR_TYPE_MASK: u32 = 0x3 << 1;
+
+pub const COMMAND_IO_SPACE: u16 = 1 << 0;
+pub const COMMAND_MEMORY_SPACE: u16 = 1 << 1;
+pub const COMMAND_BUS_MASTER: u16 = 1 << 2;
+pub const COMMAND_INTERRUPT_DISABLE: u16 = 1 << 10;
+
+static DEVICES: Once<Vec<PciDevice>> = Once::new();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bar {
+    Io(u16),
+    Memory(u64),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PciDevice {
+    pub bus: u8,
+    pub device: u8,
+    pub function: u8,
+    pub vendor_id: u16,
+    pub device_id: u16,
+    pub class: u8,
+    pub subclass: u8,
+    pub prog_if: u8,
+    pub header_type: u8,
+}
+
+fn config_address(bus: u8, device: u8, function: u8, offset: u8) -> u32 {
+    CONFIG_ENABLE
+        | (bus as u32) << 16
+        | (device as u32) << 11
+        | (function as u32) << 8
+        | (offset & 0xFC) as u32
+}
+
+fn read_config(bus: u8, device: u8, function: u8, offset: u8) -> u32 {
+    let mut address: Port<u32> = Port::new(CONFIG_ADDRESS);
+    let mut data: Port<u32> = Port::new(CONFIG_DATA);
+    unsafe {
+        address.write(config_address(bus, device, function, offset));
+        data.read()
+    }
+}
+
+fn write_config(bus: u8, device: u8, function: u8, offset: u8, value: u32) {
+    let mut address: Port<u32> = Port::new(CONFIG_ADDRESS);
+    let mut data: Port<u32> = Port::new(CONFIG_DATA);
+    unsafe {
+        address.write(config_address(bus, device, function, offset));
+        data.write(value);
+    }
+}
+
+impl PciDevice {
+    fn read(bus: u8, device: u8, function: u8) -> Option<Self> {
+        let ids = read_config(bus, device, function, OFFSET_VENDOR);
+        if ids as u16 == NO_VENDOR {
+            return None;
+        }
+
+        let class = read_config(bus, device, function, OFFSET_CLASS).to_le_bytes();
+        let header_type = read_config(bus, device, function, OFFSET_HEADER_TYPE).to_le_bytes()[2];
+        let [_, prog_if, subclass, class] = class;
+
+        Some(Self {
+            bus,
+            device,
+            function,
+            vendor_id: ids as u16,
+            device_id: (ids >> 16) as u16,
+            class,
+            subclass,
+            prog_if,
+            header_type,
+        })
+    }
+
+    pub fn read_config(&self, offset: u8) -> u32 {
+        read_config(self.bus, self.device, self.function, offset)
+    }
+
+    pub fn write_config(&self, offset: u8, value: u32) {
+        write_config(self.bus, self.device, self.function, offset, value)
+    }
+
+    // The status register shares the dword, its bits are cleared by writing 1 so 0 is written
+    pub fn enable(&self, command: u16) {
+        let current = self.read_config(OFFSET_COMMAND) as u16;
+        self.write_config(OFFSET_COMMAND, (current | command) as u32);
+    }
+
+    pub fn bar(&self, index: u8) -> Option<Bar> {
+        if self.header_type & HEADER_TYPE_MASK != HEADER_TYPE_GENERAL || index >= BAR_COUNT {
+            return None;
+        }
+
+        let offset = OFFSET_BARS + index * 4;
+        let low = self.read_config(offset);
+        let bar = match low & BAR_IO != 0 {
+            true => Bar::Io((low & !0x3) as u16),
+            false => {
+                let mut addr = (low & !0xF) as u64;
+                if low & BAR_TYPE_MASK == BAR_64_BIT && index + 1 < BAR_COUNT {
+                    addr |= (self.read_config(offset + 4) as u64) << 32;
+                }
+                Bar::Memory(addr)
+            }
+        };
+
+        match bar {
+            Bar::Io(0) | Bar::Memory(0) => None,
+            bar => Some(bar),
+        }
+    }
+
+    pub fn vendor_name(&self) -> &'static str {
+        match self.vendor_id {
+            0x1022 => "AMD",
+            0x10DE => "NVIDIA",
+            0x10EC => "Realtek",
+            0x1234 => "QEMU",
+            0x1AF4 | 0x1B36 => "Red Hat",
+            0x8086 => "Intel",
+            _ => "Unknown vendor",
+        }
+    }
+
+    pub fn class_name(&self) -> &'static str {
+        match (self.class, self.subclass) {
+            (0x01, 0x00) => "SCSI storage controller",
+            (0x01, 0x01) => "IDE controller",
+            (0x01, 0x06) => "SATA controller",
+            (0x01, 0x08) => "NVMe controller",
+            (0x01, _) => "Storage controller",
+            (0x02, 0x00) => "Ethernet controller",
+            (0x02, _) => "Network controller",
+            (0x03, 0x00) => "VGA controller",
+            (0x03, _) => "Display controller",
+            (0x04, _) => "Multimedia controller",
+            (0x06, 0x00) => "Host bridge",
+            (0x06, 0x01) => "ISA bridge",
+            (0x06, 0x04) => "PCI bridge",
+            (0x06, _) => "Bridge",
+            (0x0C, 0x03) => "USB controller",
+            (0x0C, 0x05) => "SMBus controller",
+            (0x0C, _) => "Serial bus controller",
+            _ => "Unknown device",
+        }
+    }
+}
+
+// Every bus is checked instead of following the bridges, it only takes a few milliseconds
+pub fn scan() -> Vec<PciDevice> {
+    let mut devices = Vec::new();
+
+    for bus in 0..BUS_COUNT {
+        let bus = bus as u8;
+        for device in 0..DEVICES_PER_BUS {
+            let first = match PciDevice::read(bus, device, 0) {
+                Some(first) => first,
+                None => continue,
+            };
+            let functions = match first.header_type & HEADER_MULTI_FUNCTION != 0 {
+                true => FUNCTIONS_PER_DEVICE,
+                false => 1,
+            };
+
+            devices.push(first);
+            for function in 1..functions {
+                devices.extend(PciDevice::read(bus, device, function));
+            }
+        }
+    }
+
+    devices
+}
+
+// Needs the heap
+pub fn init() {
+    let devices = scan();
+    for device in devices.iter() {
+        info!(
+            "PCI {:02x}:{:02x}.{} {:04x}:{:04x} {} {}",
+            device.bus,
+            device.device,
+            device.function,
+            device.vendor_id,
+            device.device_id,
+            device.vendor_name(),
+            device.class_name()
+        );
+    }
+    DEVICES.call_once(|| devices);
+}
+
+// Empty before `init`
+pub fn devices() -> &'static [PciDevice] {
+    DEVICES.get().map_or(&[], |devices| devices.as_slice())
+}
+
+pub fn find(vendor_id: u16, device_id: u16) -> impl Iterator<Item = &'static PciDevice> {
+    devices()
+        .iter()
+        .filter(move |device| device.vendor_id == vendor_id && device.device_id == device_id)
+}
+
+#[test_case]
+fn test_scan() {
+    // QEMU's i440FX machine always has its host bridge first
+    let devices = scan();
+    let host_bridge = devices.first().expect("no PCI device found");
+    assert_eq!((host_bridge.class, host_bridge.subclass), (0x06, 0x00));
+    assert_eq!(host_bridge.class_name(), "Host bridge");
+}
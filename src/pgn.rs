@@ -0,0 +1,474 @@
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::fmt;
+
+use cozy_chess::{Board, Color, Move};
+use engine::Eval;
+
+use crate::{
+    entities::chessboard::is_checkmate,
+    fs::{self, FsError},
+    info,
+    notation::{parse_move, to_san, NotationError},
+    rtc::DateTime,
+    serial_println, warn,
+};
+
+// Finished games are written there, one file per game named after when it ended
+const GAMES_DIR: &str = "/games";
+// Copied on the host with mtools, the menu then offers to load it
+pub const IMPORT_PATH: &str = "/import.pgn";
+
+// Movetext lines are wrapped like most programs do, the standard allows up to 255
+const LINE_LENGTH: usize = 80;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GameResult {
+    WhiteWins,
+    BlackWins,
+    Draw,
+    Unfinished,
+}
+
+impl GameResult {
+    // Only checkmate and stalemate end a game here
+    pub fn of(board: &Board) -> Self {
+        match (is_checkmate(board), board.checkers().is_empty()) {
+            (false, _) => GameResult::Unfinished,
+            (true, true) => GameResult::Draw,
+            (true, false) => match board.side_to_move() {
+                Color::White => GameResult::BlackWins,
+                Color::Black => GameResult::WhiteWins,
+            },
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            GameResult::WhiteWins => "1-0",
+            GameResult::BlackWins => "0-1",
+            GameResult::Draw => "1/2-1/2",
+            GameResult::Unfinished => "*",
+        }
+    }
+
+    fn parse(text: &str) -> Option<Self> {
+        match text {
+            "1-0" => Some(GameResult::WhiteWins),
+            "0-1" => Some(GameResult::BlackWins),
+            "1/2-1/2" => Some(GameResult::Draw),
+            "*" => Some(GameResult::Unfinished),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PgnError {
+    Tag,
+    Fen,
+    Move(usize, NotationError), // Index of the move in the game
+}
+
+impl fmt::Display for PgnError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PgnError::Tag => write!(f, "invalid tag"),
+            PgnError::Fen => write!(f, "invalid FEN tag"),
+            PgnError::Move(index, err) => write!(f, "move {}: {}", index + 1, err.message()),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Pgn {
+    // In the order they are written, the Seven Tag Roster first
+    pub tags: Vec<(String, String)>,
+    pub start: Board,
+    pub moves: Vec<Move>,
+    // For each move, what the engine thought of it from the side that played it
+    pub evals: Vec<Option<Eval>>,
+    pub result: GameResult,
+}
+
+impl Pgn {
+    pub fn new(start: Board, moves: Vec<Move>, evals: Vec<Option<Eval>>, date: DateTime) -> Self {
+        let mut board = start.clone();
+        for &mv in &moves {
+            board.play_unchecked(mv);
+        }
+        let result = GameResult::of(&board);
+
+        let date = format!("{:04}.{:02}.{:02}", date.year, date.month, date.day);
+        let mut tags = Vec::new();
+        for (name, value) in [
+            ("Event", "BMC-OS game"),
+            ("Site", "BMC-OS"),
+            ("Date", date.as_str()),
+            ("Round", "-"),
+            ("White", "?"),
+            ("Black", "?"),
+            ("Result", result.as_str()),
+        ] {
+            tags.push((name.to_string(), value.to_string()));
+        }
+
+        // Only needed when the game didn't start from the initial position
+        let fen = format!("{}", start);
+        if fen != format!("{}", Board::default()) {
+            tags.push(("SetUp".to_string(), "1".to_string()));
+            tags.push(("FEN".to_string(), fen));
+        }
+
+        Self {
+            tags,
+            start,
+            moves,
+            evals,
+            result,
+        }
+    }
+
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|(tag, _)| tag == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn set_tag(&mut self, name: &str, value: &str) {
+        match self.tags.iter_mut().find(|(tag, _)| tag == name) {
+            Some((_, old)) => *old = value.to_string(),
+            None => self.tags.push((name.to_string(), value.to_string())),
+        }
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        for (name, value) in &self.tags {
+            let value = value.replace('\\', "\\\\").replace('"', "\\\"");
+            text.push_str(&format!("[{} \"{}\"]\n", name, value));
+        }
+        text.push('\n');
+
+        let mut tokens = Vec::new();
+        let mut board = self.start.clone();
+        for (i, &mv) in self.moves.iter().enumerate() {
+            let color = board.side_to_move();
+            let number = board.fullmove_number();
+            match (color, i) {
+                (Color::White, _) => tokens.push(format!("{}.", number)),
+                (Color::Black, 0) => tokens.push(format!("{}...", number)),
+                (Color::Black, _) => {}
+            }
+
+            tokens.push(to_san(&board, mv));
+            if let Some(eval) = self.evals.get(i).copied().flatten() {
+                tokens.push(format!("{{{}}}", eval_comment(eval, color)));
+            }
+            board.play_unchecked(mv);
+        }
+        tokens.push(self.result.as_str().to_string());
+
+        let mut line_length = 0;
+        for token in tokens {
+            if line_length > 0 && line_length + 1 + token.len() > LINE_LENGTH {
+                text.push('\n');
+                line_length = 0;
+            } else if line_length > 0 {
+                text.push(' ');
+                line_length += 1;
+            }
+            text.push_str(&token);
+            line_length += token.len();
+        }
+        text.push('\n');
+
+        text
+    }
+
+    // Reads the first game of the text, variations and annotations are skipped
+    pub fn parse(text: &str) -> Result<Self, PgnError> {
+        let mut tags = Vec::new();
+        let mut rest = text.trim_start();
+        while let Some(tag) = rest.strip_prefix('[') {
+            let (tag, after) = parse_tag(tag).ok_or(PgnError::Tag)?;
+            tags.push(tag);
+            rest = after.trim_start();
+        }
+
+        let start = match tags.iter().find(|(name, _)| name == "FEN") {
+            Some((_, fen)) => fen.parse::<Board>().map_err(|_| PgnError::Fen)?,
+            None => Board::default(),
+        };
+
+        let mut board = start.clone();
+        let mut moves = Vec::new();
+        let mut evals = Vec::new();
+        let mut result = None;
+        let mut chars = rest.char_indices().peekable();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '{' => {
+                    let end = rest[i..].find('}').map_or(rest.len(), |end| i + end);
+                    // An eval comment belongs to the move right before it
+                    let color = !board.side_to_move();
+                    if let (Some(last), Some(eval)) =
+                        (evals.last_mut(), parse_eval(&rest[i + 1..end], color))
+                    {
+                        *last = Some(eval);
+                    }
+                    while chars.next_if(|&(j, _)| j <= end).is_some() {}
+                }
+                ';' => {
+                    while chars.next_if(|&(_, c)| c != '\n').is_some() {}
+                }
+                '(' => {
+                    let mut depth = 1;
+                    while depth > 0 {
+                        match chars.next() {
+                            Some((_, '(')) => depth += 1,
+                            Some((_, ')')) => depth -= 1,
+                            Some(_) => {}
+                            None => break,
+                        }
+                    }
+                }
+                c if is_delimiter(c) => {}
+                _ => {
+                    let mut end = i + c.len_utf8();
+                    while let Some((j, c)) = chars.next_if(|&(_, c)| !is_delimiter(c)) {
+                        end = j + c.len_utf8();
+                    }
+                    let token = &rest[i..end];
+
+                    if let Some(res) = GameResult::parse(token) {
+                        result = Some(res);
+                        break;
+                    }
+
+                    // Move numbers and NAGs, a move can follow the number without a space
+                    let digits = token.trim_start_matches(|c: char| c.is_ascii_digit());
+                    let token = match digits.strip_prefix('.') {
+                        Some(after) => after.trim_start_matches('.'),
+                        None => token,
+                    };
+                    if token.is_empty() || token.starts_with('$') {
+                        continue;
+                    }
+
+                    let mv = parse_move(&board, token)
+                        .map_err(|err| PgnError::Move(moves.len(), err))?;
+                    board.play_unchecked(mv);
+                    moves.push(mv);
+                    evals.push(None);
+                }
+            }
+        }
+
+        let result = result
+            .or_else(|| {
+                let (_, value) = tags.iter().find(|(name, _)| name == "Result")?;
+                GameResult::parse(value)
+            })
+            .unwrap_or(GameResult::Unfinished);
+
+        Ok(Self {
+            tags,
+            start,
+            moves,
+            evals,
+            result,
+        })
+    }
+}
+
+fn is_delimiter(c: char) -> bool {
+    c.is_whitespace() || matches!(c, '{' | '}' | '(' | ')' | ';')
+}
+
+// `Name "value"]` with the opening bracket already removed, and what follows it
+fn parse_tag(text: &str) -> Option<((String, String), &str)> {
+    let text = text.trim_start();
+    let name_end = text.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))?;
+    let (name, rest) = text.split_at(name_end);
+    let mut chars = rest.trim_start().strip_prefix('"')?.char_indices();
+
+    let mut value = String::new();
+    loop {
+        match chars.next()? {
+            (_, '\\') => value.push(chars.next()?.1),
+            (_, '"') => {
+                let rest = chars.as_str().trim_start().strip_prefix(']')?;
+                return Some(((name.to_string(), value), rest));
+            }
+            (_, c) => value.push(c),
+        }
+    }
+}
+
+// Written from White's side like other programs do, in pawns or as moves to mate
+fn eval_comment(eval: Eval, color: Color) -> String {
+    let eval = match color {
+        Color::White => eval,
+        Color::Black => -eval,
+    };
+    match eval {
+        Eval::MateIn(x) => format!("#{}", x),
+        Eval::MatedIn(x) => format!("#-{}", x),
+        Eval::CentiPawn(x) => {
+            let sign = if x < 0 { '-' } else { '+' };
+            format!("{}{}.{:02}", sign, x.abs() / 100, x.abs() % 100)
+        }
+    }
+}
+
+fn parse_eval(comment: &str, color: Color) -> Option<Eval> {
+    let comment = comment.trim();
+    let eval = match comment.strip_prefix('#') {
+        Some(mate) => match mate.strip_prefix('-') {
+            Some(x) => Eval::MatedIn(x.parse().ok()?),
+            None => Eval::MateIn(mate.parse().ok()?),
+        },
+        None => {
+            let (negative, value) = match comment.strip_prefix('-') {
+                Some(value) => (true, value),
+                None => (false, comment.strip_prefix('+').unwrap_or(comment)),
+            };
+            let (pawns, cents) = value.split_once('.')?;
+            if cents.len() != 2 {
+                return None;
+            }
+            let x = pawns.parse::<i32>().ok()? * 100 + cents.parse::<i32>().ok()?;
+            Eval::CentiPawn(if negative { -x } else { x })
+        }
+    };
+
+    Some(match color {
+        Color::White => eval,
+        Color::Black => -eval,
+    })
+}
+
+// Printed to the serial port so it can be copied from the terminal, and kept on the disk
+pub fn export(pgn: &Pgn, date: DateTime) {
+    let text = pgn.to_text();
+    info!("PGN of the game:");
+    serial_println!("{}", text);
+
+    let path = format!(
+        "{}/{:04}{:02}{:02}-{:02}{:02}{:02}.pgn",
+        GAMES_DIR, date.year, date.month, date.day, date.hour, date.minute, date.second
+    );
+    let res = match fs::create_dir(GAMES_DIR) {
+        Ok(()) | Err(FsError::AlreadyExists) => fs::write_file(&path, text.as_bytes()),
+        Err(err) => Err(err),
+    };
+    match res {
+        Ok(()) => info!("Game saved to {}", path),
+        Err(FsError::NoDisk) => {}
+        Err(err) => warn!("Game can't be saved to {}: {}", path, err),
+    }
+}
+
+pub fn import() -> Option<Pgn> {
+    let data = match fs::read_file(IMPORT_PATH) {
+        Ok(data) => data,
+        Err(FsError::NotFound | FsError::NoDisk) => return None,
+        Err(err) => {
+            warn!("{} can't be read: {}", IMPORT_PATH, err);
+            return None;
+        }
+    };
+
+    let text = String::from_utf8_lossy(&data);
+    match Pgn::parse(&text) {
+        Ok(pgn) => Some(pgn),
+        Err(err) => {
+            warn!("{} can't be parsed: {}", IMPORT_PATH, err);
+            None
+        }
+    }
+}
+
+#[test_case]
+fn test_pgn_round_trip() {
+    let mut board = Board::default();
+    let mut moves = Vec::new();
+    for text in ["f3", "e5", "g4", "Qh4"] {
+        let mv = parse_move(&board, text).unwrap();
+        board.play(mv);
+        moves.push(mv);
+    }
+    let evals = [None, Some(Eval::CentiPawn(-35)), None, Some(Eval::MateIn(1))];
+
+    let date = DateTime {
+        year: 2023,
+        month: 4,
+        day: 1,
+        hour: 12,
+        minute: 0,
+        second: 0,
+    };
+    let mut pgn = Pgn::new(Board::default(), moves.clone(), evals.to_vec(), date);
+    pgn.set_tag("White", "Fool \"the\" player");
+    assert_eq!(pgn.result, GameResult::BlackWins);
+
+    let text = pgn.to_text();
+    assert!(text.starts_with("[Event \"BMC-OS game\"]\n[Site \"BMC-OS\"]\n[Date \"2023.04.01\"]"));
+    assert!(text.contains("[White \"Fool \\\"the\\\" player\"]"));
+    assert!(text.ends_with("\n\n1. f3 e5 {+0.35} 2. g4 Qh4# {#-1} 0-1\n"));
+
+    let parsed = Pgn::parse(&text).unwrap();
+    assert_eq!(parsed.moves, moves);
+    assert_eq!(parsed.result, GameResult::BlackWins);
+    assert_eq!(parsed.tag("White"), Some("Fool \"the\" player"));
+    let values: Vec<_> = parsed.evals.iter().map(|eval| eval.map(Eval::value)).collect();
+    let expected: Vec<_> = evals.iter().map(|eval| eval.map(Eval::value)).collect();
+    assert_eq!(values, expected);
+}
+
+#[test_case]
+fn test_parse_pgn_movetext() {
+    // Numbers glued to moves, variations, NAGs, line comments and a setup position
+    let text = "[Event \"?\"]\n[FEN \"4k3/8/8/8/8/8/4P3/4K3 b - - 0 40\"]\n\n\
+                40... Kd7 ; the king comes closer\n41.e4 $1 (41. Kd2) Ke6 {no eval} *";
+    let parsed = Pgn::parse(text).unwrap();
+    assert_eq!(parsed.moves.len(), 3);
+    assert_eq!(parsed.result, GameResult::Unfinished);
+    assert!(parsed.evals.iter().all(Option::is_none));
+    assert!(parsed.to_text().ends_with("\n\n40... Kd7 41. e4 Ke6 *\n"));
+
+    let illegal = "1. e4 e5 2. Ke3";
+    assert!(matches!(Pgn::parse(illegal), Err(PgnError::Move(2, NotationError::Illegal))));
+}
+
+#[test_case]
+fn test_parse_long_pgn() {
+    // Longer than the engine's history, as an imported game can be
+    let mut board = Board::default();
+    let mut moves = Vec::new();
+    for text in ["Nc3", "Nc6", "Nb1", "Nb8"].iter().cycle().take(400) {
+        let mv = parse_move(&board, text).unwrap();
+        board.play(mv);
+        moves.push(mv);
+    }
+
+    let date = DateTime {
+        year: 2023,
+        month: 4,
+        day: 1,
+        hour: 12,
+        minute: 0,
+        second: 0,
+    };
+    let text = Pgn::new(Board::default(), moves.clone(), Vec::new(), date).to_text();
+
+    let pgn = Pgn::parse(&text).unwrap();
+    assert_eq!(pgn.moves.len(), 400);
+    assert_eq!(pgn.moves, moves);
+    assert_eq!(pgn.result, GameResult::Unfinished);
+}
@@ -0,0 +1,154 @@
+use core::{arch::asm, hint::spin_loop, ptr};
+
+use x86_64::{
+    instructions::{hlt, interrupts, port::Port, tables::lidt},
+    structures::DescriptorTablePointer,
+    PhysAddr, VirtAddr,
+};
+
+use crate::{acpi, error, info, memory, warn};
+
+// https://wiki.osdev.org/Shutdown
+const SLP_TYP_SHIFT: u16 = 10;
+const SLP_TYP_MASK: u16 = 0b111 << SLP_TYP_SHIFT;
+const SLP_EN: u16 = 1 << 13;
+const SCI_EN: u16 = 1 << 0;
+
+const ADDRESS_SPACE_MEMORY: u8 = 0;
+const ADDRESS_SPACE_IO: u8 = 1;
+
+// https://wiki.osdev.org/Reboot
+const KEYBOARD_CONTROLLER_STATUS: u16 = 0x64;
+const KEYBOARD_CONTROLLER_INPUT_FULL: u8 = 1 << 1;
+const KEYBOARD_CONTROLLER_RESET: u8 = 0xFE;
+
+const RETRIES: usize = 100_000;
+
+// Ports that power off emulators without ACPI: QEMU, Bochs and older QEMU, VirtualBox
+const EMULATOR_SHUTDOWN: [(u16, u16); 3] = [(0x604, 0x2000), (0xB004, 0x2000), (0x4004, 0x3400)];
+
+pub fn shutdown() -> ! {
+    info!("Shutting down");
+    interrupts::disable();
+
+    match unsafe { acpi_shutdown() } {
+        Ok(()) => warn!("ACPI shutdown did not power off"),
+        Err(err) => warn!("ACPI shutdown failed: {}", err),
+    }
+
+    // Still running, either way the emulators get a chance
+    for (port, value) in EMULATOR_SHUTDOWN {
+        unsafe { Port::<u16>::new(port).write(value) };
+    }
+    error!("Could not shut down");
+
+    loop {
+        hlt();
+    }
+}
+
+pub fn reboot() -> ! {
+    info!("Restarting");
+    interrupts::disable();
+
+    unsafe {
+        if let Some(fadt) = acpi::fadt() {
+            acpi_reset(&fadt);
+        }
+        keyboard_controller_reset();
+
+        // Last resort, a triple fault always resets the CPU
+        warn!("Reset failed, triple faulting");
+        triple_fault()
+    }
+}
+
+unsafe fn acpi_shutdown() -> Result<(), &'static str> {
+    let fadt = acpi::fadt().ok_or("no FADT")?;
+    let (slp_typ_a, slp_typ_b) =
+        acpi::parse_s5(acpi::table_bytes(fadt.dsdt)).ok_or("no \\_S5 object in the DSDT")?;
+
+    if fadt.pm1a_control == 0 {
+        return Err("no PM1a control block");
+    }
+
+    enable_acpi_mode(&fadt)?;
+
+    enter_sleep_state(fadt.pm1a_control as u16, slp_typ_a);
+    if fadt.pm1b_control != 0 {
+        enter_sleep_state(fadt.pm1b_control as u16, slp_typ_b);
+    }
+
+    // The machine can take a moment to power off
+    for _ in 0..RETRIES {
+        spin_loop();
+    }
+
+    Ok(())
+}
+
+// The other bits of the register, like SCI_EN, are kept
+unsafe fn enter_sleep_state(pm1_control: u16, slp_typ: u8) {
+    let mut port = Port::<u16>::new(pm1_control);
+    let value = port.read() & !SLP_TYP_MASK;
+    port.write(value | (slp_typ as u16) << SLP_TYP_SHIFT | SLP_EN);
+}
+
+// The firmware could still be handling power management itself
+unsafe fn enable_acpi_mode(fadt: &acpi::Fadt) -> Result<(), &'static str> {
+    let mut pm1a_control = Port::<u16>::new(fadt.pm1a_control as u16);
+    if pm1a_control.read() & SCI_EN != 0 {
+        return Ok(());
+    }
+
+    if fadt.smi_command == 0 || fadt.acpi_enable == 0 {
+        return Err("ACPI mode can't be enabled");
+    }
+    Port::<u8>::new(fadt.smi_command as u16).write(fadt.acpi_enable);
+
+    for _ in 0..RETRIES {
+        if pm1a_control.read() & SCI_EN != 0 {
+            return Ok(());
+        }
+        spin_loop();
+    }
+
+    Err("timed out enabling ACPI mode")
+}
+
+unsafe fn acpi_reset(fadt: &acpi::Fadt) {
+    let (register, value) = match fadt.reset_register {
+        Some(reset) => reset,
+        None => return,
+    };
+
+    match register.address_space {
+        ADDRESS_SPACE_IO => Port::<u8>::new(register.address as u16).write(value),
+        ADDRESS_SPACE_MEMORY => {
+            let addr = memory::map_physical_region(PhysAddr::new(register.address), 1);
+            ptr::write_volatile(addr.as_mut_ptr::<u8>(), value);
+        }
+        _ => {}
+    }
+}
+
+// Pulses the CPU reset line through the 8042 keyboard controller
+unsafe fn keyboard_controller_reset() {
+    let mut status = Port::<u8>::new(KEYBOARD_CONTROLLER_STATUS);
+    for _ in 0..RETRIES {
+        if status.read() & KEYBOARD_CONTROLLER_INPUT_FULL == 0 {
+            break;
+        }
+        spin_loop();
+    }
+    status.write(KEYBOARD_CONTROLLER_RESET);
+}
+
+unsafe fn triple_fault() -> ! {
+    let empty = DescriptorTablePointer {
+        limit: 0,
+        base: VirtAddr::new(0),
+    };
+    lidt(&empty);
+    asm!("int3", options(noreturn));
+}
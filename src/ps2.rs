@@ -0,0 +1,401 @@
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use x86_64::instructions::port::Port;
+
+use crate::{info, warn};
+
+// https://wiki.osdev.org/%228042%22_PS/2_Controller
+const DATA_PORT: u16 = 0x60;
+// Status register when read, command register when written
+const STATUS_PORT: u16 = 0x64;
+
+const STATUS_OUTPUT_FULL: u8 = 1 << 0;
+const STATUS_INPUT_FULL: u8 = 1 << 1;
+
+const READ_CONFIG: u8 = 0x20;
+const WRITE_CONFIG: u8 = 0x60;
+const DISABLE_SECOND_PORT: u8 = 0xA7;
+const ENABLE_SECOND_PORT: u8 = 0xA8;
+const TEST_SECOND_PORT: u8 = 0xA9;
+const SELF_TEST: u8 = 0xAA;
+const TEST_FIRST_PORT: u8 = 0xAB;
+const DISABLE_FIRST_PORT: u8 = 0xAD;
+const ENABLE_FIRST_PORT: u8 = 0xAE;
+const WRITE_SECOND_PORT: u8 = 0xD4;
+
+const CONFIG_FIRST_INTERRUPT: u8 = 1 << 0;
+const CONFIG_SECOND_INTERRUPT: u8 = 1 << 1;
+const CONFIG_SECOND_CLOCK_DISABLED: u8 = 1 << 5;
+// The keyboard handler decodes scancode set 1, which the controller translates to
+const CONFIG_TRANSLATION: u8 = 1 << 6;
+
+const SELF_TEST_PASSED: u8 = 0x55;
+const PORT_TEST_PASSED: u8 = 0x00;
+
+// https://wiki.osdev.org/PS/2_Keyboard#Commands and https://wiki.osdev.org/PS/2_Mouse
+const DEVICE_RESET: u8 = 0xFF;
+const DEVICE_SET_DEFAULTS: u8 = 0xF6;
+const DEVICE_ENABLE_REPORTING: u8 = 0xF4;
+const DEVICE_SET_SAMPLE_RATE: u8 = 0xF3;
+const DEVICE_GET_ID: u8 = 0xF2;
+
+const DEVICE_ACK: u8 = 0xFA;
+const DEVICE_RESEND: u8 = 0xFE;
+const DEVICE_RESET_PASSED: u8 = 0xAA;
+
+// Setting these sample rates in a row switches an IntelliMouse to 4 byte packets
+// with the wheel movement, its ID then changes to 3
+const INTELLIMOUSE_SAMPLE_RATES: [u8; 3] = [200, 100, 80];
+const INTELLIMOUSE_ID: u8 = 3;
+
+const PACKET_SIZE: usize = 3;
+const WHEEL_PACKET_SIZE: usize = 4;
+
+const PACKET_LEFT_BUTTON: u8 = 1 << 0;
+const PACKET_RIGHT_BUTTON: u8 = 1 << 1;
+const PACKET_MIDDLE_BUTTON: u8 = 1 << 2;
+const PACKET_ALWAYS_SET: u8 = 1 << 3;
+const PACKET_X_SIGN: u8 = 1 << 4;
+const PACKET_Y_SIGN: u8 = 1 << 5;
+const PACKET_X_OVERFLOW: u8 = 1 << 6;
+const PACKET_Y_OVERFLOW: u8 = 1 << 7;
+
+const RETRIES: usize = 3;
+// Interrupts are still disabled during the initialization, so the timeouts are counted
+// in status reads, each taking about a microsecond on real hardware
+const TIMEOUT_READS: usize = 100_000;
+// A device reset runs its self test, which can take up to a second
+const RESET_TIMEOUT_READS: usize = 1_000_000;
+// Bytes left in the output buffer by the firmware
+const MAX_FLUSHED_BYTES: usize = 32;
+
+static MOUSE_AVAILABLE: AtomicBool = AtomicBool::new(false);
+static WHEEL_AVAILABLE: AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Device {
+    Keyboard,
+    Mouse,
+}
+
+impl Device {
+    fn name(self) -> &'static str {
+        match self {
+            Device::Keyboard => "keyboard",
+            Device::Mouse => "mouse",
+        }
+    }
+}
+
+struct Controller {
+    data: Port<u8>,
+    status: Port<u8>,
+}
+
+impl Controller {
+    fn new() -> Self {
+        Self {
+            data: Port::new(DATA_PORT),
+            status: Port::new(STATUS_PORT),
+        }
+    }
+
+    fn wait(&mut self, mask: u8, set: bool, reads: usize) -> Result<(), &'static str> {
+        for _ in 0..reads {
+            let status = unsafe { self.status.read() };
+            if (status & mask != 0) == set {
+                return Ok(());
+            }
+        }
+        Err("timed out")
+    }
+
+    fn command(&mut self, command: u8) -> Result<(), &'static str> {
+        self.wait(STATUS_INPUT_FULL, false, TIMEOUT_READS)?;
+        unsafe { self.status.write(command) };
+        Ok(())
+    }
+
+    fn write(&mut self, byte: u8) -> Result<(), &'static str> {
+        self.wait(STATUS_INPUT_FULL, false, TIMEOUT_READS)?;
+        unsafe { self.data.write(byte) };
+        Ok(())
+    }
+
+    fn read_with_timeout(&mut self, reads: usize) -> Result<u8, &'static str> {
+        self.wait(STATUS_OUTPUT_FULL, true, reads)?;
+        Ok(unsafe { self.data.read() })
+    }
+
+    fn read(&mut self) -> Result<u8, &'static str> {
+        self.read_with_timeout(TIMEOUT_READS)
+    }
+
+    fn command_with_response(&mut self, command: u8) -> Result<u8, &'static str> {
+        self.command(command)?;
+        self.read()
+    }
+
+    fn flush(&mut self) {
+        for _ in 0..MAX_FLUSHED_BYTES {
+            if unsafe { self.status.read() } & STATUS_OUTPUT_FULL == 0 {
+                return;
+            }
+            unsafe { self.data.read() };
+        }
+    }
+
+    fn read_config(&mut self) -> Result<u8, &'static str> {
+        self.command_with_response(READ_CONFIG)
+    }
+
+    fn write_config(&mut self, config: u8) -> Result<(), &'static str> {
+        self.command(WRITE_CONFIG)?;
+        self.write(config)
+    }
+
+    fn send(&mut self, device: Device, byte: u8) -> Result<(), &'static str> {
+        if device == Device::Mouse {
+            self.command(WRITE_SECOND_PORT)?;
+        }
+        self.write(byte)
+    }
+
+    // Sends a command to a device until it is acknowledged
+    fn device_command(&mut self, device: Device, command: u8) -> Result<(), &'static str> {
+        for _ in 0..RETRIES {
+            self.send(device, command)?;
+            match self.read()? {
+                DEVICE_ACK => return Ok(()),
+                DEVICE_RESEND => continue,
+                _ => return Err("unexpected response"),
+            }
+        }
+        Err("command not acknowledged")
+    }
+
+    fn reset_device(&mut self, device: Device) -> Result<(), &'static str> {
+        self.device_command(device, DEVICE_RESET)?;
+        match self.read_with_timeout(RESET_TIMEOUT_READS)? {
+            DEVICE_RESET_PASSED => {}
+            _ => return Err("self test failed"),
+        }
+
+        // The mouse follows with its ID, and some keyboards do too
+        self.flush();
+        Ok(())
+    }
+
+    fn init_device(&mut self, device: Device) -> Result<(), &'static str> {
+        let mut attempt = 1;
+        while let Err(err) = self.reset_device(device) {
+            warn!(
+                "PS/2 {} reset failed (attempt {}/{}): {}",
+                device.name(),
+                attempt,
+                RETRIES,
+                err
+            );
+            if attempt == RETRIES {
+                return Err(err);
+            }
+            attempt += 1;
+            self.flush();
+        }
+
+        self.device_command(device, DEVICE_SET_DEFAULTS)?;
+        if device == Device::Mouse {
+            match self.enable_wheel() {
+                Ok(true) => {
+                    info!("PS/2 mouse has a wheel");
+                    WHEEL_AVAILABLE.store(true, Ordering::Relaxed);
+                }
+                Ok(false) => {}
+                Err(err) => warn!("PS/2 mouse wheel detection failed: {}", err),
+            }
+        }
+        self.device_command(device, DEVICE_ENABLE_REPORTING)
+    }
+
+    fn enable_wheel(&mut self) -> Result<bool, &'static str> {
+        for rate in INTELLIMOUSE_SAMPLE_RATES {
+            self.device_command(Device::Mouse, DEVICE_SET_SAMPLE_RATE)?;
+            self.device_command(Device::Mouse, rate)?;
+        }
+        self.device_command(Device::Mouse, DEVICE_GET_ID)?;
+        Ok(self.read()? == INTELLIMOUSE_ID)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MouseState {
+    buttons: u8,
+    x: i16,
+    y: i16,
+    wheel: i8,
+}
+
+impl MouseState {
+    pub fn left_button_down(&self) -> bool {
+        self.buttons & PACKET_LEFT_BUTTON != 0
+    }
+
+    pub fn right_button_down(&self) -> bool {
+        self.buttons & PACKET_RIGHT_BUTTON != 0
+    }
+
+    pub fn middle_button_down(&self) -> bool {
+        self.buttons & PACKET_MIDDLE_BUTTON != 0
+    }
+
+    // Positive to the right
+    pub fn get_x(&self) -> i16 {
+        self.x
+    }
+
+    // Positive upwards
+    pub fn get_y(&self) -> i16 {
+        self.y
+    }
+
+    // Negative when scrolling up
+    pub fn wheel(&self) -> i8 {
+        self.wheel
+    }
+}
+
+// Assembles the bytes sent by the mouse into packets
+pub struct MouseDecoder {
+    packet: [u8; WHEEL_PACKET_SIZE],
+    len: usize,
+}
+
+impl MouseDecoder {
+    pub const fn new() -> Self {
+        Self {
+            packet: [0; WHEEL_PACKET_SIZE],
+            len: 0,
+        }
+    }
+
+    pub fn add_byte(&mut self, byte: u8) -> Option<MouseState> {
+        // Resynchronizing if a byte was lost, the first byte always has this bit set
+        if self.len == 0 && byte & PACKET_ALWAYS_SET == 0 {
+            return None;
+        }
+
+        self.packet[self.len] = byte;
+        self.len += 1;
+
+        let size = match has_wheel() {
+            true => WHEEL_PACKET_SIZE,
+            false => PACKET_SIZE,
+        };
+        if self.len < size {
+            return None;
+        }
+        self.len = 0;
+
+        Some(decode_packet(&self.packet[..size]))
+    }
+}
+
+fn decode_packet(packet: &[u8]) -> MouseState {
+    let flags = packet[0];
+
+    // 9 bit two's complement values, the sign bit is in the first byte
+    let x = packet[1] as i16 - if flags & PACKET_X_SIGN != 0 { 0x100 } else { 0 };
+    let y = packet[2] as i16 - if flags & PACKET_Y_SIGN != 0 { 0x100 } else { 0 };
+
+    MouseState {
+        buttons: flags & (PACKET_LEFT_BUTTON | PACKET_RIGHT_BUTTON | PACKET_MIDDLE_BUTTON),
+        x: if flags & PACKET_X_OVERFLOW != 0 { 0 } else { x },
+        y: if flags & PACKET_Y_OVERFLOW != 0 { 0 } else { y },
+        wheel: packet.get(3).map_or(0, |&z| z as i8),
+    }
+}
+
+// Interrupts must be disabled, the devices are reset and their interrupts enabled only
+// once they responded. A missing mouse is not an error, `has_mouse` tells if it can be used.
+pub fn init() -> Result<(), &'static str> {
+    let mut controller = Controller::new();
+
+    controller.command(DISABLE_FIRST_PORT)?;
+    controller.command(DISABLE_SECOND_PORT)?;
+    controller.flush();
+
+    let mut config = controller.read_config()?;
+    config &= !(CONFIG_FIRST_INTERRUPT | CONFIG_SECOND_INTERRUPT);
+    config |= CONFIG_TRANSLATION;
+    controller.write_config(config)?;
+
+    if controller.command_with_response(SELF_TEST)? != SELF_TEST_PASSED {
+        return Err("controller self test failed");
+    }
+    // The self test can reset the controller on some hardware
+    controller.write_config(config)?;
+
+    // The second port exists if enabling it starts its clock
+    controller.command(ENABLE_SECOND_PORT)?;
+    let dual_channel = controller.read_config()? & CONFIG_SECOND_CLOCK_DISABLED == 0;
+    controller.command(DISABLE_SECOND_PORT)?;
+
+    let keyboard_port = controller.command_with_response(TEST_FIRST_PORT)? == PORT_TEST_PASSED;
+    let mouse_port =
+        dual_channel && controller.command_with_response(TEST_SECOND_PORT)? == PORT_TEST_PASSED;
+
+    if keyboard_port {
+        controller.command(ENABLE_FIRST_PORT)?;
+        config |= CONFIG_FIRST_INTERRUPT;
+        match controller.init_device(Device::Keyboard) {
+            Ok(()) => info!("PS/2 keyboard initialized"),
+            // Some keyboards don't answer a reset but still send scancodes
+            Err(err) => warn!("PS/2 keyboard failed to initialize, keeping it enabled: {}", err),
+        }
+    } else {
+        warn!("PS/2 keyboard port failed its test");
+    }
+
+    if mouse_port {
+        controller.command(ENABLE_SECOND_PORT)?;
+        match controller.init_device(Device::Mouse) {
+            Ok(()) => {
+                info!("PS/2 mouse initialized");
+                config |= CONFIG_SECOND_INTERRUPT;
+                config &= !CONFIG_SECOND_CLOCK_DISABLED;
+                MOUSE_AVAILABLE.store(true, Ordering::Relaxed);
+            }
+            Err(err) => {
+                warn!("PS/2 mouse failed to initialize: {}", err);
+                controller.command(DISABLE_SECOND_PORT)?;
+                config |= CONFIG_SECOND_CLOCK_DISABLED;
+            }
+        }
+    } else {
+        warn!("No PS/2 mouse port");
+    }
+
+    controller.flush();
+    controller.write_config(config)
+}
+
+pub fn has_mouse() -> bool {
+    MOUSE_AVAILABLE.load(Ordering::Relaxed)
+}
+
+pub fn has_wheel() -> bool {
+    WHEEL_AVAILABLE.load(Ordering::Relaxed)
+}
+
+#[test_case]
+fn test_decode_packet() {
+    // Right button, moving left and down, scrolling up
+    let state = decode_packet(&[0b0011_1010, 0xFB, 0xFE, 0xFF]);
+    assert!(state.right_button_down() && !state.left_button_down());
+    assert_eq!((state.get_x(), state.get_y(), state.wheel()), (-5, -2, -1));
+
+    // Overflowed movements are dropped
+    let state = decode_packet(&[0b0100_1001, 0xFF, 0x03]);
+    assert!(state.left_button_down());
+    assert_eq!((state.get_x(), state.get_y(), state.wheel()), (0, 3, 0));
+}
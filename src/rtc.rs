@@ -0,0 +1,249 @@
+use core::fmt;
+
+use alloc::{format, string::String};
+use spin::Mutex;
+use x86_64::instructions::{interrupts, port::Port};
+
+// https://wiki.osdev.org/CMOS
+const CMOS_ADDRESS: u16 = 0x70;
+const CMOS_DATA: u16 = 0x71;
+
+const REG_SECONDS: u8 = 0x00;
+const REG_MINUTES: u8 = 0x02;
+const REG_HOURS: u8 = 0x04;
+const REG_DAY: u8 = 0x07;
+const REG_MONTH: u8 = 0x08;
+const REG_YEAR: u8 = 0x09;
+const REG_CENTURY: u8 = 0x32;
+const REG_STATUS_A: u8 = 0x0A;
+const REG_STATUS_B: u8 = 0x0B;
+
+const STATUS_A_UPDATE_IN_PROGRESS: u8 = 1 << 7;
+const STATUS_B_24_HOUR: u8 = 1 << 1;
+const STATUS_B_BINARY: u8 = 1 << 2;
+
+const HOUR_PM: u8 = 1 << 7;
+
+static CMOS: Mutex<Cmos> = Mutex::new(Cmos::new());
+
+struct Cmos {
+    address: Port<u8>,
+    data: Port<u8>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct RawDateTime {
+    second: u8,
+    minute: u8,
+    hour: u8,
+    day: u8,
+    month: u8,
+    year: u8,
+    century: u8,
+}
+
+// Field order matters for the derived ordering
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct DateTime {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+}
+
+impl Cmos {
+    const fn new() -> Self {
+        Self {
+            address: Port::new(CMOS_ADDRESS),
+            data: Port::new(CMOS_DATA),
+        }
+    }
+
+    fn read(&mut self, register: u8) -> u8 {
+        unsafe {
+            self.address.write(register);
+            self.data.read()
+        }
+    }
+
+    fn update_in_progress(&mut self) -> bool {
+        self.read(REG_STATUS_A) & STATUS_A_UPDATE_IN_PROGRESS != 0
+    }
+
+    fn read_raw(&mut self) -> RawDateTime {
+        while self.update_in_progress() {
+            core::hint::spin_loop();
+        }
+
+        RawDateTime {
+            second: self.read(REG_SECONDS),
+            minute: self.read(REG_MINUTES),
+            hour: self.read(REG_HOURS),
+            day: self.read(REG_DAY),
+            month: self.read(REG_MONTH),
+            year: self.read(REG_YEAR),
+            century: self.read(REG_CENTURY),
+        }
+    }
+}
+
+// Reads the current date and time from the CMOS, assumes the RTC is set to UTC
+pub fn now() -> DateTime {
+    interrupts::without_interrupts(|| {
+        let mut cmos = CMOS.lock();
+
+        // Reading until two reads in a row agree, so an update can't happen halfway through
+        let mut raw = cmos.read_raw();
+        loop {
+            let next = cmos.read_raw();
+            if next == raw {
+                break;
+            }
+            raw = next;
+        }
+
+        let status_b = cmos.read(REG_STATUS_B);
+        decode(raw, status_b)
+    })
+}
+
+fn decode(raw: RawDateTime, status_b: u8) -> DateTime {
+    let binary = status_b & STATUS_B_BINARY != 0;
+    let convert = |value: u8| {
+        if binary {
+            value
+        } else {
+            bcd_to_binary(value)
+        }
+    };
+
+    let pm = raw.hour & HOUR_PM != 0;
+    let mut hour = convert(raw.hour & !HOUR_PM);
+    if status_b & STATUS_B_24_HOUR == 0 {
+        hour = to_24_hour(hour, pm);
+    }
+
+    // The century register is not guaranteed to exist, assuming the 2000s if it doesn't
+    let century = match convert(raw.century) {
+        c @ 19..=99 => c as u16,
+        _ => 20,
+    };
+
+    DateTime {
+        year: century * 100 + convert(raw.year) as u16,
+        month: convert(raw.month),
+        day: convert(raw.day),
+        hour,
+        minute: convert(raw.minute),
+        second: convert(raw.second),
+    }
+}
+
+pub fn bcd_to_binary(value: u8) -> u8 {
+    (value >> 4) * 10 + (value & 0x0F)
+}
+
+pub fn to_24_hour(hour: u8, pm: bool) -> u8 {
+    match (hour, pm) {
+        (12, false) => 0,
+        (12, true) => 12,
+        (hour, false) => hour,
+        (hour, true) => hour + 12,
+    }
+}
+
+impl DateTime {
+    // http://howardhinnant.github.io/date_algorithms.html#days_from_civil
+    pub fn unix_timestamp(&self) -> u64 {
+        let year = self.year as i64 - (self.month <= 2) as i64;
+        let era = year.div_euclid(400);
+        let year_of_era = year - era * 400;
+        let month = self.month as i64;
+        let shifted_month = if month > 2 { month - 3 } else { month + 9 };
+        let day_of_year = (153 * shifted_month + 2) / 5 + self.day as i64 - 1;
+        let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+        let days = era * 146097 + day_of_era - 719468;
+
+        let seconds = days * 86400
+            + self.hour as i64 * 3600
+            + self.minute as i64 * 60
+            + self.second as i64;
+        seconds.max(0) as u64
+    }
+
+    pub fn time_string(&self) -> String {
+        format!("{:02}:{:02}:{:02}", self.hour, self.minute, self.second)
+    }
+}
+
+impl fmt::Display for DateTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )
+    }
+}
+
+#[test_case]
+fn test_bcd_12_hour_decode() {
+    let raw = RawDateTime {
+        second: 0x59,
+        minute: 0x30,
+        hour: HOUR_PM | 0x11,
+        day: 0x28,
+        month: 0x02,
+        year: 0x23,
+        century: 0x20,
+    };
+
+    let date = decode(raw, 0);
+    assert_eq!(
+        date,
+        DateTime {
+            year: 2023,
+            month: 2,
+            day: 28,
+            hour: 23,
+            minute: 30,
+            second: 59
+        }
+    );
+}
+
+#[test_case]
+fn test_binary_24_hour_decode() {
+    let raw = RawDateTime {
+        second: 5,
+        minute: 4,
+        hour: 0,
+        day: 1,
+        month: 1,
+        year: 99,
+        century: 0,
+    };
+
+    let date = decode(raw, STATUS_B_BINARY | STATUS_B_24_HOUR);
+    assert_eq!(date.year, 2099);
+    assert_eq!(date.hour, 0);
+    // Written on the stack so the test doesn't depend on the heap
+    let mut text = arrayvec::ArrayString::<32>::new();
+    fmt::write(&mut text, format_args!("{}", date)).unwrap();
+    assert_eq!(text.as_str(), "2099-01-01 00:04:05");
+}
+
+#[test_case]
+fn test_unix_timestamp() {
+    let date = DateTime {
+        year: 2023,
+        month: 3,
+        day: 1,
+        hour: 12,
+        minute: 0,
+        second: 0,
+    };
+    assert_eq!(date.unix_timestamp(), 1_677_672_000);
+}
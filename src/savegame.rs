@@ -0,0 +1,200 @@
+use alloc::{format, string::String, vec::Vec};
+
+use cozy_chess::{Board, Color, Move};
+
+use crate::{
+    fs::{self, FsError},
+    game::engine_history,
+    notation::parse_move,
+    settings::ENGINE_LEVELS,
+    warn,
+};
+
+// The game in progress, in the same "key=value" text format as the settings
+const SAVE_PATH: &str = "/game.sav";
+const VERSION: u32 = 1;
+
+#[derive(Debug, Clone)]
+pub struct SavedGame {
+    // The FEN also holds the halfmove clock and the move number
+    pub start: Board,
+    pub moves: Vec<Move>,
+    pub engine_level: u8,
+    pub user_color: Color,
+}
+
+impl SavedGame {
+    // None if the record is corrupt, from another version, or has an illegal move
+    pub fn parse(text: &str) -> Option<Self> {
+        let mut version = None;
+        let mut start = None;
+        let mut moves = None;
+        let mut engine_level = None;
+        let mut user_color = None;
+
+        let lines = text.lines().map(str::trim).filter(|line| !line.is_empty());
+        for line in lines {
+            let (key, value) = line.split_once('=')?;
+            let value = value.trim();
+            match key.trim() {
+                "version" => version = value.parse::<u32>().ok(),
+                "start" => start = Some(value.parse::<Board>().ok()?),
+                "moves" => moves = Some(value),
+                "engine_level" => {
+                    let level = value.parse::<u8>().ok();
+                    engine_level = Some(level.filter(|level| ENGINE_LEVELS.contains(level))?);
+                }
+                "color" => {
+                    user_color = Some(match value {
+                        "white" => Color::White,
+                        "black" => Color::Black,
+                        _ => return None,
+                    })
+                }
+                _ => return None,
+            }
+        }
+
+        if version != Some(VERSION) {
+            return None;
+        }
+
+        let start = start?;
+        let mut board = start.clone();
+        let mut parsed = Vec::new();
+        for text in moves?.split_whitespace() {
+            let mv = parse_move(&board, text).ok()?;
+            board.play_unchecked(mv);
+            parsed.push(mv);
+        }
+
+        Some(Self {
+            start,
+            moves: parsed,
+            engine_level: engine_level?,
+            user_color: user_color?,
+        })
+    }
+
+    pub fn to_text(&self) -> String {
+        let color = match self.user_color {
+            Color::White => "white",
+            Color::Black => "black",
+        };
+        let moves: Vec<String> = self.moves.iter().map(|mv| format!("{}", mv)).collect();
+
+        format!(
+            "version={}\nstart={}\nmoves={}\nengine_level={}\ncolor={}\n",
+            VERSION,
+            self.start,
+            moves.join(" "),
+            self.engine_level,
+            color
+        )
+    }
+
+    // The current position, and the hashes of the positions before it for repetitions
+    pub fn replay(&self) -> (Board, Vec<u64>) {
+        let mut board = self.start.clone();
+        let mut history = Vec::with_capacity(self.moves.len());
+        for &mv in &self.moves {
+            history.push(board.hash());
+            board.play_unchecked(mv);
+        }
+        (board, history)
+    }
+}
+
+pub fn load() -> Option<SavedGame> {
+    let data = match fs::read_file(SAVE_PATH) {
+        Ok(data) => data,
+        Err(FsError::NotFound | FsError::NoDisk) => return None,
+        Err(err) => {
+            warn!("Saved game can't be read: {}", err);
+            return None;
+        }
+    };
+
+    let game = core::str::from_utf8(&data).ok().and_then(SavedGame::parse);
+    if game.is_none() {
+        warn!("Saved game in {} is corrupt", SAVE_PATH);
+    }
+    game
+}
+
+// False if there is no game on the disk to continue after this
+pub fn save(game: &SavedGame) -> bool {
+    match fs::write_file(SAVE_PATH, game.to_text().as_bytes()) {
+        Ok(()) => true,
+        Err(FsError::NoDisk) => false,
+        Err(err) => {
+            warn!("Game can't be saved: {}", err);
+            false
+        }
+    }
+}
+
+// Finished games can't be continued
+pub fn delete() {
+    match fs::remove(SAVE_PATH) {
+        Ok(()) | Err(FsError::NotFound | FsError::NoDisk) => {}
+        Err(err) => warn!("Saved game can't be deleted: {}", err),
+    }
+}
+
+#[test_case]
+fn test_parse_saved_game() {
+    let mut board = Board::default();
+    let mut moves = Vec::new();
+    for text in ["e4", "e5", "Nf3", "Nc6", "Bc4", "Nf6", "O-O"] {
+        let mv = parse_move(&board, text).unwrap();
+        board.play(mv);
+        moves.push(mv);
+    }
+
+    let game = SavedGame {
+        start: Board::default(),
+        moves,
+        engine_level: 4,
+        user_color: Color::Black,
+    };
+    let parsed = SavedGame::parse(&game.to_text()).unwrap();
+    assert_eq!(parsed.moves, game.moves);
+    assert_eq!((parsed.engine_level, parsed.user_color), (4, Color::Black));
+
+    let (replayed, history) = parsed.replay();
+    assert_eq!(replayed.hash(), board.hash());
+    assert_eq!(history.len(), 7);
+    assert_eq!(history[0], Board::default().hash());
+
+    // An illegal move makes the whole record corrupt
+    let illegal = game.to_text().replace("e2e4", "e2e5");
+    assert!(SavedGame::parse(&illegal).is_none());
+    assert!(SavedGame::parse("version=1\n").is_none());
+}
+
+#[test_case]
+fn test_resume_long_game() {
+    // The knights going back and forth, 300 plies without a capture or a pawn move
+    let mut board = Board::default();
+    let mut moves = Vec::new();
+    for text in ["Nf3", "Nf6", "Ng1", "Ng8"].iter().cycle().take(300) {
+        let mv = parse_move(&board, text).unwrap();
+        board.play(mv);
+        moves.push(mv);
+    }
+
+    let game = SavedGame {
+        start: Board::default(),
+        moves,
+        engine_level: 3,
+        user_color: Color::White,
+    };
+    let (replayed, history) = SavedGame::parse(&game.to_text()).unwrap().replay();
+    assert_eq!(history.len(), 300);
+
+    // What is given to the engine fits next to its search, and ends with the latest positions
+    let tail = engine_history(&history, &replayed);
+    assert!(tail.len() + engine::engine::MAX_DEPTH as usize <= 256);
+    assert_eq!(tail.last(), history.last());
+}
@@ -0,0 +1,126 @@
+use core::fmt;
+
+use lazy_static::lazy_static;
+use spin::Mutex;
+use x86_64::instructions::{interrupts, port::Port};
+
+// https://wiki.osdev.org/Serial_Ports
+const COM1: u16 = 0x3F8;
+
+const LINE_STATUS_DATA_READY: u8 = 1 << 0;
+const LINE_STATUS_TRANSMIT_EMPTY: u8 = 1 << 5;
+
+lazy_static! {
+    pub static ref SERIAL1: Mutex<SerialPort> = {
+        let mut serial_port = SerialPort::new(COM1);
+        serial_port.init();
+        Mutex::new(serial_port)
+    };
+}
+
+#[macro_export]
+macro_rules! serial_print {
+    ($($arg:tt)*) => ($crate::serial::_print(format_args!($($arg)*)));
+}
+
+#[macro_export]
+macro_rules! serial_println {
+    () => ($crate::serial_print!("\n"));
+    ($($arg:tt)*) => ($crate::serial_print!("{}\n", format_args!($($arg)*)));
+}
+
+pub struct SerialPort {
+    data: Port<u8>,
+    interrupt_enable: Port<u8>,
+    fifo_control: Port<u8>,
+    line_control: Port<u8>,
+    modem_control: Port<u8>,
+    line_status: Port<u8>,
+    working: bool,
+}
+
+impl SerialPort {
+    pub const fn new(base: u16) -> Self {
+        Self {
+            data: Port::new(base),
+            interrupt_enable: Port::new(base + 1),
+            fifo_control: Port::new(base + 2),
+            line_control: Port::new(base + 3),
+            modem_control: Port::new(base + 4),
+            line_status: Port::new(base + 5),
+            working: false,
+        }
+    }
+
+    pub fn init(&mut self) {
+        unsafe {
+            self.interrupt_enable.write(0x00); // Disable all interrupts
+            self.line_control.write(0x80); // Enable DLAB to set the baud rate divisor
+            self.data.write(0x03); // Divisor low byte, 38400 baud
+            self.interrupt_enable.write(0x00); // Divisor high byte
+            self.line_control.write(0x03); // 8 bits, no parity, one stop bit
+            self.fifo_control.write(0xC7); // Enable and clear FIFOs, 14 bytes threshold
+            self.modem_control.write(0x0B); // DTR, RTS and OUT2 set
+
+            // Loopback test to know if there is actually a chip there
+            self.modem_control.write(0x1E);
+            self.data.write(0xAE);
+            self.working = self.data.read() == 0xAE;
+
+            // Normal operation mode
+            self.modem_control.write(0x0F);
+        }
+    }
+
+    pub fn is_working(&self) -> bool {
+        self.working
+    }
+
+    pub fn send(&mut self, byte: u8) {
+        if !self.working {
+            return;
+        }
+
+        while !self.line_status_has(LINE_STATUS_TRANSMIT_EMPTY) {
+            core::hint::spin_loop();
+        }
+
+        unsafe { self.data.write(byte) }
+    }
+
+    pub fn try_receive(&mut self) -> Option<u8> {
+        if !self.working || !self.line_status_has(LINE_STATUS_DATA_READY) {
+            return None;
+        }
+
+        Some(unsafe { self.data.read() })
+    }
+
+    fn line_status_has(&mut self, flag: u8) -> bool {
+        unsafe { self.line_status.read() & flag != 0 }
+    }
+}
+
+impl fmt::Write for SerialPort {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for byte in s.bytes() {
+            self.send(byte);
+        }
+        Ok(())
+    }
+}
+
+pub fn _print(args: fmt::Arguments) {
+    use core::fmt::Write;
+    interrupts::without_interrupts(|| {
+        SERIAL1
+            .lock()
+            .write_fmt(args)
+            .expect("Printing to serial failed");
+    });
+}
+
+// Only for the crash screen, the lock could be held by the code that crashed
+pub unsafe fn force_unlock() {
+    SERIAL1.force_unlock();
+}
@@ -0,0 +1,197 @@
+use alloc::{format, string::String};
+use core::ops::RangeInclusive;
+
+use cozy_chess::Color;
+
+use crate::{
+    fs::{self, FsError},
+    info, warn,
+};
+
+// Plain text so it can be edited on the host with mtools, one "key=value" per line
+const SETTINGS_PATH: &str = "/settings.cfg";
+// Bumped when the meaning of a key changes, older records are then ignored
+const VERSION: u32 = 1;
+
+pub const ENGINE_LEVELS: RangeInclusive<u8> = 1..=7;
+// The table is halved at boot until it fits in memory
+pub const TT_SIZES_KB: RangeInclusive<usize> = 8..=64 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Theme {
+    Classic,
+    Green,
+    Blue,
+}
+
+impl Theme {
+    const ALL: [Theme; 3] = [Theme::Classic, Theme::Green, Theme::Blue];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Theme::Classic => "Classic",
+            Theme::Green => "Green",
+            Theme::Blue => "Blue",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|theme| theme.name().eq_ignore_ascii_case(name))
+    }
+
+    pub fn next(&self) -> Self {
+        let index = Self::ALL.iter().position(|theme| theme == self).unwrap();
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Settings {
+    pub engine_level: u8,
+    pub user_color: Color,
+    pub theme: Theme,
+    pub sound: bool,
+    pub tt_size_kb: usize,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            engine_level: 3,
+            user_color: Color::White,
+            theme: Theme::Classic,
+            sound: true,
+            tt_size_kb: 10,
+        }
+    }
+}
+
+fn parse_color(value: &str) -> Option<Color> {
+    match value {
+        "white" => Some(Color::White),
+        "black" => Some(Color::Black),
+        _ => None,
+    }
+}
+
+fn parse_bool(value: &str) -> Option<bool> {
+    match value {
+        "on" => Some(true),
+        "off" => Some(false),
+        _ => None,
+    }
+}
+
+impl Settings {
+    // None if the record is corrupt or from another version, missing keys keep their default
+    pub fn parse(text: &str) -> Option<Self> {
+        let mut settings = Self::default();
+        let mut version = None;
+
+        let lines = text.lines().map(str::trim).filter(|line| !line.is_empty());
+        for line in lines {
+            let (key, value) = line.split_once('=')?;
+            let value = value.trim();
+            match key.trim() {
+                "version" => version = value.parse::<u32>().ok(),
+                "engine_level" => {
+                    settings.engine_level = value
+                        .parse()
+                        .ok()
+                        .filter(|level| ENGINE_LEVELS.contains(level))?
+                }
+                "color" => settings.user_color = parse_color(value)?,
+                "theme" => settings.theme = Theme::from_name(value)?,
+                "sound" => settings.sound = parse_bool(value)?,
+                "tt_size_kb" => {
+                    settings.tt_size_kb = value
+                        .parse()
+                        .ok()
+                        .filter(|size| TT_SIZES_KB.contains(size))?
+                }
+                _ => return None,
+            }
+        }
+
+        match version {
+            Some(VERSION) => Some(settings),
+            _ => None,
+        }
+    }
+
+    pub fn to_text(&self) -> String {
+        let color = match self.user_color {
+            Color::White => "white",
+            Color::Black => "black",
+        };
+        let sound = match self.sound {
+            true => "on",
+            false => "off",
+        };
+
+        format!(
+            "version={}\nengine_level={}\ncolor={}\ntheme={}\nsound={}\ntt_size_kb={}\n",
+            VERSION,
+            self.engine_level,
+            color,
+            self.theme.name().to_ascii_lowercase(),
+            sound,
+            self.tt_size_kb
+        )
+    }
+}
+
+// The defaults are used without a disk, or when the record can't be read
+pub fn load() -> Settings {
+    let data = match fs::read_file(SETTINGS_PATH) {
+        Ok(data) => data,
+        Err(FsError::NotFound | FsError::NoDisk) => return Settings::default(),
+        Err(err) => {
+            warn!("Settings can't be read: {}", err);
+            return Settings::default();
+        }
+    };
+
+    let settings = core::str::from_utf8(&data).ok().and_then(Settings::parse);
+    match settings {
+        Some(settings) => {
+            info!("Settings loaded from {}", SETTINGS_PATH);
+            settings
+        }
+        None => {
+            warn!("Settings in {} are corrupt, using the defaults", SETTINGS_PATH);
+            Settings::default()
+        }
+    }
+}
+
+pub fn save(settings: &Settings) {
+    match fs::write_file(SETTINGS_PATH, settings.to_text().as_bytes()) {
+        Ok(()) | Err(FsError::NoDisk) => {}
+        Err(err) => warn!("Settings can't be saved: {}", err),
+    }
+}
+
+#[test_case]
+fn test_parse_settings() {
+    let settings = Settings {
+        engine_level: 5,
+        user_color: Color::Black,
+        theme: Theme::Blue,
+        sound: false,
+        tt_size_kb: 1024,
+    };
+    assert_eq!(Settings::parse(&settings.to_text()), Some(settings));
+
+    // Missing keys keep their default, anything unexpected is corrupt
+    let partial = Settings::parse("version=1\ncolor=black\n").unwrap();
+    assert_eq!(partial.user_color, Color::Black);
+    assert_eq!(partial.engine_level, Settings::default().engine_level);
+    assert_eq!(Settings::parse("color=black\n"), None);
+    assert_eq!(Settings::parse("version=2\n"), None);
+    assert_eq!(Settings::parse("version=1\nengine_level=9\n"), None);
+    assert_eq!(Settings::parse("version=1\nvolume=11\n"), None);
+    assert_eq!(Settings::parse("version=1\ngarbage\n"), None);
+}
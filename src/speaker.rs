@@ -0,0 +1,48 @@
+use core::time::Duration;
+
+use x86_64::instructions::port::Port;
+
+use crate::time;
+
+// https://wiki.osdev.org/PC_Speaker, the speaker is driven by channel 2 of the PIT
+const PIT_FREQUENCY: u32 = 1_193_182;
+const PIT_CHANNEL_2: u16 = 0x42;
+const PIT_COMMAND: u16 = 0x43;
+const SPEAKER_CONTROL: u16 = 0x61;
+
+// Channel 2, lobyte/hibyte access, mode 3 (square wave), binary
+const PIT_SQUARE_WAVE: u8 = 0b1011_0110;
+// Connects the channel 2 output to the speaker
+const SPEAKER_ENABLE: u8 = 0b11;
+
+pub fn play(frequency: u32) {
+    let divisor = (PIT_FREQUENCY / frequency.max(1)).clamp(1, u16::MAX as u32) as u16;
+
+    let mut command: Port<u8> = Port::new(PIT_COMMAND);
+    let mut data: Port<u8> = Port::new(PIT_CHANNEL_2);
+    let mut control: Port<u8> = Port::new(SPEAKER_CONTROL);
+
+    unsafe {
+        command.write(PIT_SQUARE_WAVE);
+        data.write(divisor as u8);
+        data.write((divisor >> 8) as u8);
+
+        let value = control.read();
+        control.write(value | SPEAKER_ENABLE);
+    }
+}
+
+pub fn stop() {
+    let mut control: Port<u8> = Port::new(SPEAKER_CONTROL);
+    unsafe {
+        let value = control.read();
+        control.write(value & !SPEAKER_ENABLE);
+    }
+}
+
+// Meant to be spawned, so the game keeps running while it plays
+pub async fn beep(frequency: u32, duration: Duration) {
+    play(frequency);
+    time::sleep_async(duration).await;
+    stop();
+}
@@ -0,0 +1,357 @@
+use alloc::{
+    string::{String, ToString},
+    vec::Vec,
+};
+
+use x86_64::instructions::port::Port;
+
+use crate::{
+    storage::{check_request, BlockDevice, BlockError, BLOCK_SIZE},
+    warn,
+};
+
+// https://wiki.osdev.org/ATA_PIO_Mode
+const PRIMARY_BUS: Bus = Bus {
+    io: 0x1F0,
+    control: 0x3F6,
+};
+const SECONDARY_BUS: Bus = Bus {
+    io: 0x170,
+    control: 0x376,
+};
+
+// Registers, as offsets from the I/O base
+const REG_DATA: u16 = 0;
+const REG_ERROR: u16 = 1;
+const REG_SECTOR_COUNT: u16 = 2;
+const REG_LBA_LOW: u16 = 3;
+const REG_LBA_MID: u16 = 4;
+const REG_LBA_HIGH: u16 = 5;
+const REG_DRIVE: u16 = 6;
+// Status when read, command when written
+const REG_STATUS: u16 = 7;
+
+const STATUS_ERROR: u8 = 1 << 0;
+const STATUS_DATA_REQUEST: u8 = 1 << 3;
+const STATUS_DRIVE_FAULT: u8 = 1 << 5;
+const STATUS_BUSY: u8 = 1 << 7;
+// Read on a bus with no drives, the lines are pulled up
+const STATUS_FLOATING: u8 = 0xFF;
+
+// Disables the interrupts of the bus, the drives are polled
+const CONTROL_NO_INTERRUPTS: u8 = 1 << 1;
+
+const DRIVE_ALWAYS_SET: u8 = 0xA0;
+const DRIVE_LBA: u8 = 1 << 6;
+const DRIVE_SLAVE: u8 = 1 << 4;
+
+const COMMAND_READ: u8 = 0x20;
+const COMMAND_READ_EXT: u8 = 0x24;
+const COMMAND_WRITE: u8 = 0x30;
+const COMMAND_WRITE_EXT: u8 = 0x34;
+const COMMAND_FLUSH: u8 = 0xE7;
+const COMMAND_FLUSH_EXT: u8 = 0xEA;
+const COMMAND_IDENTIFY: u8 = 0xEC;
+
+// Words of the IDENTIFY data
+const IDENTIFY_MODEL: core::ops::Range<usize> = 27..47;
+const IDENTIFY_LBA28_SECTORS: usize = 60;
+const IDENTIFY_COMMAND_SETS: usize = 83;
+const IDENTIFY_LBA48_SECTORS: usize = 100;
+const COMMAND_SET_LBA48: u16 = 1 << 10;
+
+const LBA28_LIMIT: u64 = 1 << 28;
+// A sector count of 0 means 256 with LBA28, keeping it the same with LBA48
+const MAX_SECTORS_PER_COMMAND: usize = 256;
+
+// Status reads, each taking about a microsecond
+const TIMEOUT_READS: usize = 1_000_000;
+
+#[derive(Debug, Clone, Copy)]
+struct Bus {
+    io: u16,
+    control: u16,
+}
+
+impl Bus {
+    fn register(&self, offset: u16) -> Port<u8> {
+        Port::new(self.io + offset)
+    }
+
+    fn read(&self, offset: u16) -> u8 {
+        unsafe { self.register(offset).read() }
+    }
+
+    fn write(&self, offset: u16, value: u8) {
+        unsafe { self.register(offset).write(value) }
+    }
+
+    fn status(&self) -> u8 {
+        self.read(REG_STATUS)
+    }
+
+    fn disable_interrupts(&self) {
+        unsafe { Port::<u8>::new(self.control).write(CONTROL_NO_INTERRUPTS) }
+    }
+
+    // Drives take 400ns to put their status on the bus after being selected,
+    // reading the alternate status doesn't change anything and takes 100ns
+    fn delay(&self) {
+        let mut alternate_status: Port<u8> = Port::new(self.control);
+        for _ in 0..4 {
+            unsafe { alternate_status.read() };
+        }
+    }
+
+    fn select(&self, drive: u8) {
+        self.write(REG_DRIVE, drive);
+        self.delay();
+    }
+
+    fn check_error(&self, status: u8) -> Result<(), BlockError> {
+        match status & (STATUS_ERROR | STATUS_DRIVE_FAULT) {
+            0 => Ok(()),
+            _ => Err(BlockError::Device(self.read(REG_ERROR))),
+        }
+    }
+
+    fn wait_not_busy(&self) -> Result<u8, BlockError> {
+        for _ in 0..TIMEOUT_READS {
+            let status = self.status();
+            if status & STATUS_BUSY == 0 {
+                return Ok(status);
+            }
+        }
+        Err(BlockError::Timeout)
+    }
+
+    // Waits until the drive is ready to transfer a sector
+    fn wait_data(&self) -> Result<(), BlockError> {
+        for _ in 0..TIMEOUT_READS {
+            let status = self.status();
+            if status & STATUS_BUSY != 0 {
+                continue;
+            }
+            self.check_error(status)?;
+            if status & STATUS_DATA_REQUEST != 0 {
+                return Ok(());
+            }
+        }
+        Err(BlockError::Timeout)
+    }
+}
+
+pub struct AtaDrive {
+    bus: Bus,
+    slave: bool,
+    sectors: u64,
+    lba48: bool,
+    name: String,
+}
+
+// The primary master is the boot image QEMU starts from, it is never used for storage
+pub fn probe() -> Vec<AtaDrive> {
+    let drives = [(PRIMARY_BUS, true), (SECONDARY_BUS, false), (SECONDARY_BUS, true)];
+
+    let mut found = Vec::new();
+    for (bus, slave) in drives {
+        match AtaDrive::identify(bus, slave) {
+            Ok(Some(drive)) => found.push(drive),
+            Ok(None) => {}
+            Err(err) => warn!("ATA drive at {:#x} failed to identify: {}", bus.io, err),
+        }
+    }
+    found
+}
+
+impl AtaDrive {
+    fn drive_bits(&self) -> u8 {
+        match self.slave {
+            true => DRIVE_SLAVE,
+            false => 0,
+        }
+    }
+
+    // Ok(None) when there is no ATA drive, ATAPI and SATA drives aren't supported
+    fn identify(bus: Bus, slave: bool) -> Result<Option<Self>, BlockError> {
+        if bus.status() == STATUS_FLOATING {
+            return Ok(None);
+        }
+
+        bus.disable_interrupts();
+        bus.select(DRIVE_ALWAYS_SET | if slave { DRIVE_SLAVE } else { 0 });
+        for register in [REG_SECTOR_COUNT, REG_LBA_LOW, REG_LBA_MID, REG_LBA_HIGH] {
+            bus.write(register, 0);
+        }
+        bus.write(REG_STATUS, COMMAND_IDENTIFY);
+
+        if bus.status() == 0 {
+            return Ok(None);
+        }
+        bus.wait_not_busy()?;
+
+        // Set by ATAPI and SATA drives, which abort the command
+        if bus.read(REG_LBA_MID) != 0 || bus.read(REG_LBA_HIGH) != 0 {
+            return Ok(None);
+        }
+        bus.wait_data()?;
+
+        let mut data: Port<u16> = Port::new(bus.io + REG_DATA);
+        let mut identify = [0u16; 256];
+        for word in identify.iter_mut() {
+            *word = unsafe { data.read() };
+        }
+
+        let lba48 = identify[IDENTIFY_COMMAND_SETS] & COMMAND_SET_LBA48 != 0;
+        let sectors = match lba48 {
+            true => identify[IDENTIFY_LBA48_SECTORS..IDENTIFY_LBA48_SECTORS + 4]
+                .iter()
+                .rev()
+                .fold(0, |sectors, &word| sectors << 16 | word as u64),
+            false => {
+                let words = &identify[IDENTIFY_LBA28_SECTORS..IDENTIFY_LBA28_SECTORS + 2];
+                (words[1] as u64) << 16 | words[0] as u64
+            }
+        };
+
+        // The model is space padded, with the two bytes of each word swapped
+        let model: Vec<u8> = identify[IDENTIFY_MODEL]
+            .iter()
+            .flat_map(|word| word.to_be_bytes())
+            .collect();
+        let name = String::from_utf8_lossy(&model).trim().to_string();
+
+        Ok(Some(Self {
+            bus,
+            slave,
+            sectors,
+            lba48,
+            name,
+        }))
+    }
+
+    fn send_command(&mut self, lba: u64, count: usize, command: u8) {
+        let bus = self.bus;
+        let lba_bytes = lba.to_le_bytes();
+
+        if self.lba48 {
+            bus.select(DRIVE_LBA | self.drive_bits());
+            // The high bytes go first, each register holding two bytes
+            bus.write(REG_SECTOR_COUNT, (count >> 8) as u8);
+            bus.write(REG_LBA_LOW, lba_bytes[3]);
+            bus.write(REG_LBA_MID, lba_bytes[4]);
+            bus.write(REG_LBA_HIGH, lba_bytes[5]);
+        } else {
+            let lba_top = lba_bytes[3] & 0x0F;
+            bus.select(DRIVE_ALWAYS_SET | DRIVE_LBA | self.drive_bits() | lba_top);
+        }
+
+        bus.write(REG_SECTOR_COUNT, count as u8);
+        bus.write(REG_LBA_LOW, lba_bytes[0]);
+        bus.write(REG_LBA_MID, lba_bytes[1]);
+        bus.write(REG_LBA_HIGH, lba_bytes[2]);
+        bus.write(REG_STATUS, command);
+    }
+
+    fn command(&self, lba28: u8, lba48: u8) -> u8 {
+        match self.lba48 {
+            true => lba48,
+            false => lba28,
+        }
+    }
+}
+
+impl BlockDevice for AtaDrive {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn block_count(&self) -> u64 {
+        match self.lba48 {
+            true => self.sectors,
+            false => self.sectors.min(LBA28_LIMIT),
+        }
+    }
+
+    fn read_blocks(&mut self, lba: u64, buf: &mut [u8]) -> Result<(), BlockError> {
+        check_request(self.block_count(), lba, buf.len())?;
+        let command = self.command(COMMAND_READ, COMMAND_READ_EXT);
+        let mut data: Port<u16> = Port::new(self.bus.io + REG_DATA);
+
+        let chunks = buf.chunks_mut(MAX_SECTORS_PER_COMMAND * BLOCK_SIZE);
+        for (i, chunk) in chunks.enumerate() {
+            let chunk_lba = lba + (i * MAX_SECTORS_PER_COMMAND) as u64;
+            self.send_command(chunk_lba, chunk.len() / BLOCK_SIZE, command);
+
+            for sector in chunk.chunks_mut(BLOCK_SIZE) {
+                self.bus.wait_data()?;
+                for bytes in sector.chunks_exact_mut(2) {
+                    bytes.copy_from_slice(&unsafe { data.read() }.to_le_bytes());
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn write_blocks(&mut self, lba: u64, buf: &[u8]) -> Result<(), BlockError> {
+        check_request(self.block_count(), lba, buf.len())?;
+        let command = self.command(COMMAND_WRITE, COMMAND_WRITE_EXT);
+        let mut data: Port<u16> = Port::new(self.bus.io + REG_DATA);
+
+        let chunks = buf.chunks(MAX_SECTORS_PER_COMMAND * BLOCK_SIZE);
+        for (i, chunk) in chunks.enumerate() {
+            let chunk_lba = lba + (i * MAX_SECTORS_PER_COMMAND) as u64;
+            self.send_command(chunk_lba, chunk.len() / BLOCK_SIZE, command);
+
+            for sector in chunk.chunks(BLOCK_SIZE) {
+                self.bus.wait_data()?;
+                for bytes in sector.chunks_exact(2) {
+                    unsafe { data.write(u16::from_le_bytes([bytes[0], bytes[1]])) };
+                }
+            }
+
+            let status = self.bus.wait_not_busy()?;
+            self.bus.check_error(status)?;
+        }
+
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), BlockError> {
+        let bus = self.bus;
+        bus.select(DRIVE_ALWAYS_SET | self.drive_bits());
+        bus.write(REG_STATUS, self.command(COMMAND_FLUSH, COMMAND_FLUSH_EXT));
+
+        let status = bus.wait_not_busy()?;
+        bus.check_error(status)
+    }
+}
+
+#[test_case]
+fn test_read_write() {
+    use crate::storage;
+
+    // The test disk is a scratch image, the last blocks are restored anyway
+    let res = storage::with_disk(|disk| {
+        let lba = disk.block_count() - 2;
+        let mut saved = [0u8; 2 * BLOCK_SIZE];
+        disk.read_blocks(lba, &mut saved)?;
+
+        let mut written = [0u8; 2 * BLOCK_SIZE];
+        for (i, byte) in written.iter_mut().enumerate() {
+            *byte = (i * 7) as u8;
+        }
+        disk.write_blocks(lba, &written)?;
+        disk.flush()?;
+
+        let mut read = [0u8; 2 * BLOCK_SIZE];
+        disk.read_blocks(lba, &mut read)?;
+        disk.write_blocks(lba, &saved)?;
+
+        assert_eq!(disk.read_blocks(disk.block_count(), &mut read), Err(BlockError::OutOfRange));
+        Ok::<_, BlockError>(read == written)
+    });
+
+    assert_eq!(res, Some(Ok(true)), "the test disk should be attached as the primary slave");
+}
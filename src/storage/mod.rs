@@ -0,0 +1,112 @@
+use alloc::{boxed::Box, vec::Vec};
+use core::fmt;
+
+use spin::Mutex;
+
+use crate::{info, warn};
+
+pub mod ata;
+pub mod virtio;
+
+pub const BLOCK_SIZE: usize = 512;
+
+// Disks games and settings can be stored on, in the order they were found
+static DISKS: Mutex<Vec<Box<dyn BlockDevice>>> = Mutex::new(Vec::new());
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockError {
+    OutOfRange,
+    BufferSize,
+    Timeout,
+    ReadOnly,
+    Device(u8), // Error register or request status of the device
+}
+
+impl fmt::Display for BlockError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BlockError::OutOfRange => write!(f, "block out of range"),
+            BlockError::BufferSize => write!(f, "buffer is not a whole number of blocks"),
+            BlockError::Timeout => write!(f, "device timed out"),
+            BlockError::ReadOnly => write!(f, "device is read only"),
+            BlockError::Device(error) => write!(f, "device error {:#04x}", error),
+        }
+    }
+}
+
+// A disk read and written in blocks of `BLOCK_SIZE` bytes, buffers must hold whole blocks
+pub trait BlockDevice: Send {
+    fn name(&self) -> &str;
+
+    fn block_count(&self) -> u64;
+
+    fn read_blocks(&mut self, lba: u64, buf: &mut [u8]) -> Result<(), BlockError>;
+
+    fn write_blocks(&mut self, lba: u64, buf: &[u8]) -> Result<(), BlockError>;
+
+    // Makes sure the written blocks reached the disk and not only its cache
+    fn flush(&mut self) -> Result<(), BlockError>;
+}
+
+// Checks that a request of `len` bytes starting at `lba` fits on a device of `block_count` blocks
+pub fn check_request(block_count: u64, lba: u64, len: usize) -> Result<(), BlockError> {
+    if len % BLOCK_SIZE != 0 {
+        return Err(BlockError::BufferSize);
+    }
+
+    match lba.checked_add((len / BLOCK_SIZE) as u64) {
+        Some(end) if end <= block_count => Ok(()),
+        _ => Err(BlockError::OutOfRange),
+    }
+}
+
+// ATA drives come first so the IDE test disk stays the first disk when tests also attach virtio
+pub fn init() {
+    for drive in ata::probe() {
+        register(Box::new(drive));
+    }
+    for disk in virtio::probe() {
+        register(Box::new(disk));
+    }
+
+    if DISKS.lock().is_empty() {
+        warn!("No disk found, nothing will be saved");
+    }
+}
+
+pub fn register(disk: Box<dyn BlockDevice>) {
+    info!(
+        "Disk {}: {} MiB",
+        disk.name(),
+        disk.block_count() * BLOCK_SIZE as u64 / 1024 / 1024
+    );
+    DISKS.lock().push(disk);
+}
+
+pub fn disk_count() -> usize {
+    DISKS.lock().len()
+}
+
+// Runs `f` on the first disk, if there is one
+pub fn with_disk<R>(f: impl FnOnce(&mut dyn BlockDevice) -> R) -> Option<R> {
+    with_disk_matching(|_| true, f)
+}
+
+pub fn with_disk_matching<R>(
+    filter: impl Fn(&dyn BlockDevice) -> bool,
+    f: impl FnOnce(&mut dyn BlockDevice) -> R,
+) -> Option<R> {
+    let mut disks = DISKS.lock();
+    disks
+        .iter_mut()
+        .find(|disk| filter(disk.as_ref()))
+        .map(|disk| f(disk.as_mut()))
+}
+
+#[test_case]
+fn test_check_request() {
+    assert_eq!(check_request(8, 0, 8 * BLOCK_SIZE), Ok(()));
+    assert_eq!(check_request(8, 7, 2 * BLOCK_SIZE), Err(BlockError::OutOfRange));
+    assert_eq!(check_request(8, u64::MAX, BLOCK_SIZE), Err(BlockError::OutOfRange));
+    assert_eq!(check_request(8, 0, 100), Err(BlockError::BufferSize));
+}
@@ -0,0 +1,387 @@
+use alloc::{format, string::String, vec::Vec};
+use core::{
+    ptr,
+    sync::atomic::{fence, Ordering},
+};
+
+use x86_64::{instructions::port::Port, PhysAddr, VirtAddr};
+
+use crate::{
+    memory,
+    pci::{self, Bar, PciDevice},
+    storage::{check_request, BlockDevice, BlockError, BLOCK_SIZE},
+    warn,
+};
+
+// Legacy interface of the virtio 1.1 specification (sections 2.6, 4.1.4.8 and 5.2),
+// QEMU's virtio-blk-pci is a transitional device that has it next to the modern one
+const VENDOR_ID: u16 = 0x1AF4;
+const DEVICE_ID_BLOCK: u16 = 0x1001;
+
+// Registers, as offsets from the I/O BAR
+const REG_DEVICE_FEATURES: u16 = 0x00;
+const REG_DRIVER_FEATURES: u16 = 0x04;
+const REG_QUEUE_ADDRESS: u16 = 0x08;
+const REG_QUEUE_SIZE: u16 = 0x0C;
+const REG_QUEUE_SELECT: u16 = 0x0E;
+const REG_QUEUE_NOTIFY: u16 = 0x10;
+const REG_DEVICE_STATUS: u16 = 0x12;
+// The device configuration follows, MSI-X is never enabled so it doesn't move
+const REG_CAPACITY: u16 = 0x14;
+
+const STATUS_RESET: u8 = 0;
+const STATUS_ACKNOWLEDGE: u8 = 1;
+const STATUS_DRIVER: u8 = 2;
+const STATUS_DRIVER_OK: u8 = 4;
+const STATUS_FAILED: u8 = 128;
+
+const FEATURE_READ_ONLY: u32 = 1 << 5;
+const FEATURE_FLUSH: u32 = 1 << 9;
+
+const REQUEST_IN: u32 = 0;
+const REQUEST_OUT: u32 = 1;
+const REQUEST_FLUSH: u32 = 4;
+const REQUEST_HEADER_SIZE: usize = 16;
+const REQUEST_STATUS_OK: u8 = 0;
+// Overwritten by the device when the request completes
+const REQUEST_STATUS_PENDING: u8 = 0xFF;
+
+const REQUEST_QUEUE: u16 = 0;
+
+// Descriptor: address (u64), length (u32), flags (u16), next (u16)
+const DESCRIPTOR_SIZE: usize = 16;
+const DESCRIPTOR_NEXT: u16 = 1;
+const DESCRIPTOR_DEVICE_WRITES: u16 = 2;
+// The rings start with flags (u16) and an index (u16)
+const RING_INDEX: usize = 2;
+const RING_ENTRIES: usize = 4;
+const USED_ENTRY_SIZE: usize = 8;
+const AVAILABLE_NO_INTERRUPT: u16 = 1;
+
+const HEADER_DESCRIPTOR: u16 = 0;
+const DATA_DESCRIPTOR: u16 = 1;
+const STATUS_DESCRIPTOR: u16 = 2;
+
+// The legacy interface takes the queue address as a frame number
+const FRAME_SIZE: usize = 4096;
+const QUEUE_ALIGN: usize = 4096;
+// Data goes through a bounce buffer, requests are split to fit in it
+const BUFFER_FRAMES: usize = 16;
+const MAX_BLOCKS_PER_REQUEST: usize = BUFFER_FRAMES * FRAME_SIZE / BLOCK_SIZE;
+
+// Polls of the used ring, requests are served by QEMU in a few microseconds
+const TIMEOUT_POLLS: usize = 10_000_000;
+
+fn align_up(value: usize, align: usize) -> usize {
+    (value + align - 1) / align * align
+}
+
+// Queue of the legacy layout: descriptors, available ring, then the used ring on the next page
+struct Queue {
+    size: u16,
+    available: usize,
+    used: usize,
+    len: usize,
+}
+
+impl Queue {
+    fn new(size: u16) -> Self {
+        let descriptors = size as usize * DESCRIPTOR_SIZE;
+        let available_len = RING_ENTRIES + 2 * size as usize + 2;
+        let used = align_up(descriptors + available_len, QUEUE_ALIGN);
+        let used_len = RING_ENTRIES + USED_ENTRY_SIZE * size as usize + 2;
+
+        Self {
+            size,
+            available: descriptors,
+            used,
+            len: align_up(used + used_len, QUEUE_ALIGN),
+        }
+    }
+}
+
+pub struct VirtioBlock {
+    io: u16,
+    queue: Queue,
+    // Memory shared with the device: the queue, the request header and status, then the buffer
+    memory: PhysAddr,
+    virt: VirtAddr,
+    header: usize,
+    buffer: usize,
+    last_used: u16,
+    sectors: u64,
+    read_only: bool,
+    flush_supported: bool,
+    // Set after a timeout, the device was reset and isn't used anymore
+    failed: bool,
+    name: String,
+}
+
+pub fn probe() -> Vec<VirtioBlock> {
+    pci::find(VENDOR_ID, DEVICE_ID_BLOCK)
+        .filter_map(|device| match VirtioBlock::init(device) {
+            Ok(disk) => Some(disk),
+            Err(err) => {
+                let address = (device.bus, device.device);
+                warn!("Virtio disk {:02x?} failed to initialize: {}", address, err);
+                None
+            }
+        })
+        .collect()
+}
+
+impl VirtioBlock {
+    fn init(device: &PciDevice) -> Result<Self, &'static str> {
+        let io = match device.bar(0) {
+            Some(Bar::Io(io)) => io,
+            _ => return Err("no legacy I/O registers"),
+        };
+
+        // The disk is polled like the ATA drives
+        let command = pci::COMMAND_IO_SPACE | pci::COMMAND_BUS_MASTER;
+        device.enable(command | pci::COMMAND_INTERRUPT_DISABLE);
+
+        let mut disk = Self {
+            io,
+            queue: Queue::new(0),
+            memory: PhysAddr::zero(),
+            virt: VirtAddr::zero(),
+            header: 0,
+            buffer: 0,
+            last_used: 0,
+            sectors: 0,
+            read_only: false,
+            flush_supported: false,
+            failed: false,
+            name: format!("virtio {:02x}:{:02x}.{}", device.bus, device.device, device.function),
+        };
+
+        let res = disk.negotiate();
+        if res.is_err() {
+            disk.write_u8(REG_DEVICE_STATUS, STATUS_FAILED);
+        }
+        res.map(|()| disk)
+    }
+
+    fn negotiate(&mut self) -> Result<(), &'static str> {
+        self.write_u8(REG_DEVICE_STATUS, STATUS_RESET);
+        self.write_u8(REG_DEVICE_STATUS, STATUS_ACKNOWLEDGE);
+        self.write_u8(REG_DEVICE_STATUS, STATUS_ACKNOWLEDGE | STATUS_DRIVER);
+
+        // Only flushing is used, read only disks are accepted but never written
+        let features = self.read_u32(REG_DEVICE_FEATURES);
+        self.write_u32(REG_DRIVER_FEATURES, features & FEATURE_FLUSH);
+        self.read_only = features & FEATURE_READ_ONLY != 0;
+        self.flush_supported = features & FEATURE_FLUSH != 0;
+
+        self.write_u16(REG_QUEUE_SELECT, REQUEST_QUEUE);
+        let size = self.read_u16(REG_QUEUE_SIZE);
+        if size == 0 {
+            return Err("no request queue");
+        }
+        self.queue = Queue::new(size);
+
+        self.header = self.queue.len;
+        self.buffer = self.header + FRAME_SIZE;
+        let frames = self.buffer / FRAME_SIZE + BUFFER_FRAMES;
+        let first = memory::frame_allocator()
+            .allocate_contiguous(frames)
+            .ok_or("not enough contiguous memory")?;
+        self.memory = first.start_address();
+        self.virt = memory::physical_to_virtual(self.memory);
+        unsafe { ptr::write_bytes(self.virt.as_mut_ptr::<u8>(), 0, frames * FRAME_SIZE) };
+
+        // Completions are polled
+        self.write(self.queue.available, AVAILABLE_NO_INTERRUPT);
+        let frame_number = self.memory.as_u64() / FRAME_SIZE as u64;
+        self.write_u32(REG_QUEUE_ADDRESS, frame_number as u32);
+
+        let capacity_low = self.read_u32(REG_CAPACITY) as u64;
+        let capacity_high = self.read_u32(REG_CAPACITY + 4) as u64;
+        self.sectors = capacity_high << 32 | capacity_low;
+
+        self.write_u8(REG_DEVICE_STATUS, STATUS_ACKNOWLEDGE | STATUS_DRIVER | STATUS_DRIVER_OK);
+        Ok(())
+    }
+
+    fn read_u16(&self, offset: u16) -> u16 {
+        unsafe { Port::new(self.io + offset).read() }
+    }
+
+    fn read_u32(&self, offset: u16) -> u32 {
+        unsafe { Port::new(self.io + offset).read() }
+    }
+
+    fn write_u8(&self, offset: u16, value: u8) {
+        unsafe { Port::new(self.io + offset).write(value) }
+    }
+
+    fn write_u16(&self, offset: u16, value: u16) {
+        unsafe { Port::new(self.io + offset).write(value) }
+    }
+
+    fn write_u32(&self, offset: u16, value: u32) {
+        unsafe { Port::new(self.io + offset).write(value) }
+    }
+
+    // Accesses to the shared memory are volatile, the device changes it behind our back
+    fn read<T: Copy>(&self, offset: usize) -> T {
+        unsafe { ptr::read_volatile((self.virt + offset).as_ptr()) }
+    }
+
+    fn write<T: Copy>(&self, offset: usize, value: T) {
+        unsafe { ptr::write_volatile((self.virt + offset).as_mut_ptr(), value) }
+    }
+
+    fn write_descriptor(&self, index: u16, offset: usize, len: usize, flags: u16, next: u16) {
+        let descriptor = index as usize * DESCRIPTOR_SIZE;
+        self.write(descriptor, self.memory.as_u64() + offset as u64);
+        self.write(descriptor + 8, len as u32);
+        self.write(descriptor + 12, flags);
+        self.write(descriptor + 14, next);
+    }
+
+    // Sends a request using `len` bytes of the buffer and waits for it, one request at a time
+    fn request(&mut self, kind: u32, sector: u64, len: usize) -> Result<(), BlockError> {
+        if self.failed {
+            return Err(BlockError::Timeout);
+        }
+
+        self.write(self.header, kind);
+        self.write(self.header + 4, 0u32);
+        self.write(self.header + 8, sector);
+        let status = self.header + REQUEST_HEADER_SIZE;
+        self.write(status, REQUEST_STATUS_PENDING);
+
+        let header_next = match len {
+            0 => STATUS_DESCRIPTOR,
+            _ => DATA_DESCRIPTOR,
+        };
+        self.write_descriptor(
+            HEADER_DESCRIPTOR,
+            self.header,
+            REQUEST_HEADER_SIZE,
+            DESCRIPTOR_NEXT,
+            header_next,
+        );
+        if len > 0 {
+            let flags = match kind {
+                REQUEST_IN => DESCRIPTOR_NEXT | DESCRIPTOR_DEVICE_WRITES,
+                _ => DESCRIPTOR_NEXT,
+            };
+            self.write_descriptor(DATA_DESCRIPTOR, self.buffer, len, flags, STATUS_DESCRIPTOR);
+        }
+        self.write_descriptor(STATUS_DESCRIPTOR, status, 1, DESCRIPTOR_DEVICE_WRITES, 0);
+
+        // The descriptors must be visible before the index that publishes them
+        let available_index: u16 = self.read(self.queue.available + RING_INDEX);
+        let slot = (available_index % self.queue.size) as usize;
+        self.write(self.queue.available + RING_ENTRIES + 2 * slot, HEADER_DESCRIPTOR);
+        fence(Ordering::SeqCst);
+        self.write(self.queue.available + RING_INDEX, available_index.wrapping_add(1));
+        fence(Ordering::SeqCst);
+        self.write_u16(REG_QUEUE_NOTIFY, REQUEST_QUEUE);
+
+        let mut completed = false;
+        for _ in 0..TIMEOUT_POLLS {
+            let used_index: u16 = self.read(self.queue.used + RING_INDEX);
+            if used_index != self.last_used {
+                completed = true;
+                break;
+            }
+        }
+        // The device could still complete the request later, writing to the buffer and making
+        // the next request look done. Resetting it makes it drop the queue
+        if !completed {
+            warn!("{} timed out, it won't be used anymore", self.name);
+            self.write_u8(REG_DEVICE_STATUS, STATUS_RESET);
+            self.write_u8(REG_DEVICE_STATUS, STATUS_FAILED);
+            self.failed = true;
+            return Err(BlockError::Timeout);
+        }
+        self.last_used = self.last_used.wrapping_add(1);
+        fence(Ordering::SeqCst);
+
+        match self.read::<u8>(status) {
+            REQUEST_STATUS_OK => Ok(()),
+            status => Err(BlockError::Device(status)),
+        }
+    }
+
+    fn buffer(&mut self, len: usize) -> &mut [u8] {
+        let start = (self.virt + self.buffer).as_mut_ptr();
+        unsafe { core::slice::from_raw_parts_mut(start, len) }
+    }
+}
+
+impl BlockDevice for VirtioBlock {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn block_count(&self) -> u64 {
+        self.sectors
+    }
+
+    fn read_blocks(&mut self, lba: u64, buf: &mut [u8]) -> Result<(), BlockError> {
+        check_request(self.block_count(), lba, buf.len())?;
+
+        let chunks = buf.chunks_mut(MAX_BLOCKS_PER_REQUEST * BLOCK_SIZE);
+        for (i, chunk) in chunks.enumerate() {
+            let chunk_lba = lba + (i * MAX_BLOCKS_PER_REQUEST) as u64;
+            self.request(REQUEST_IN, chunk_lba, chunk.len())?;
+            chunk.copy_from_slice(self.buffer(chunk.len()));
+        }
+
+        Ok(())
+    }
+
+    fn write_blocks(&mut self, lba: u64, buf: &[u8]) -> Result<(), BlockError> {
+        check_request(self.block_count(), lba, buf.len())?;
+        if self.read_only {
+            return Err(BlockError::ReadOnly);
+        }
+
+        let chunks = buf.chunks(MAX_BLOCKS_PER_REQUEST * BLOCK_SIZE);
+        for (i, chunk) in chunks.enumerate() {
+            let chunk_lba = lba + (i * MAX_BLOCKS_PER_REQUEST) as u64;
+            self.buffer(chunk.len()).copy_from_slice(chunk);
+            self.request(REQUEST_OUT, chunk_lba, chunk.len())?;
+        }
+
+        Ok(())
+    }
+
+    // Without the flush feature the device has no write cache
+    fn flush(&mut self) -> Result<(), BlockError> {
+        match self.flush_supported {
+            true => self.request(REQUEST_FLUSH, 0, 0),
+            false => Ok(()),
+        }
+    }
+}
+
+#[test_case]
+fn test_read_write() {
+    use crate::storage;
+    use alloc::vec;
+
+    let is_virtio = |disk: &dyn BlockDevice| disk.name().starts_with("virtio");
+    let res = storage::with_disk_matching(is_virtio, |disk| {
+        // More than a request, to check the splitting
+        let len = (MAX_BLOCKS_PER_REQUEST + 3) * BLOCK_SIZE;
+        let mut saved = vec![0u8; len];
+        disk.read_blocks(0, &mut saved)?;
+
+        let written: Vec<u8> = (0..len).map(|i| (i * 13) as u8).collect();
+        disk.write_blocks(0, &written)?;
+        disk.flush()?;
+
+        let mut read = vec![0u8; len];
+        disk.read_blocks(0, &mut read)?;
+        disk.write_blocks(0, &saved)?;
+        Ok::<_, BlockError>(read == written)
+    });
+
+    assert_eq!(res, Some(Ok(true)), "the virtio test disk should be attached");
+}
@@ -0,0 +1,130 @@
+use alloc::{collections::BTreeMap, sync::Arc, task::Wake, vec::Vec};
+use arrayvec::ArrayVec;
+use core::{
+    future::Future,
+    sync::atomic::{AtomicBool, Ordering},
+    task::{Context, Waker},
+};
+use crossbeam_queue::ArrayQueue;
+use lazy_static::lazy_static;
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+
+use crate::task::{Task, TaskId};
+
+const MAX_TASKS: usize = 100;
+
+struct TaskEntry {
+    task: Task,
+    waker: Waker,
+    state: Arc<TaskWaker>,
+}
+
+struct TaskWaker {
+    id: TaskId,
+    queued: AtomicBool,
+}
+
+lazy_static! {
+    // Wakers can be called from interrupts, so the queue has to be lock free
+    static ref TASK_QUEUE: ArrayQueue<TaskId> = ArrayQueue::new(MAX_TASKS);
+    // Only holds the tasks that are not being polled at the moment
+    static ref TASKS: Mutex<BTreeMap<TaskId, TaskEntry>> = Mutex::new(BTreeMap::new());
+    static ref RUNNING: Mutex<Vec<TaskId>> = Mutex::new(Vec::new());
+}
+
+pub fn spawn(future: impl Future<Output = ()> + 'static) {
+    let task = Task::new(future);
+    let id = task.id;
+    let state = Arc::new(TaskWaker {
+        id,
+        queued: AtomicBool::new(false),
+    });
+    let waker = Waker::from(state.clone());
+
+    let entry = TaskEntry {
+        task,
+        waker,
+        state: state.clone(),
+    };
+    if TASKS.lock().insert(id, entry).is_some() {
+        panic!("Task with the same ID already spawned");
+    }
+
+    state.wake_task();
+}
+
+pub fn run() -> ! {
+    loop {
+        run_ready_tasks();
+        sleep_if_idle();
+    }
+}
+
+// Polls the other tasks that are ready, for code that runs for a long time without returning
+// to the executor (like the engine search). Tasks higher in the call stack are not polled.
+pub fn yield_now() {
+    run_ready_tasks();
+}
+
+fn run_ready_tasks() {
+    // Tasks that were woken while they are being polled further up the stack
+    let mut deferred: ArrayVec<TaskId, MAX_TASKS> = ArrayVec::new();
+
+    while let Some(id) = TASK_QUEUE.pop() {
+        let entry = TASKS.lock().remove(&id);
+        let mut entry = match entry {
+            Some(entry) => entry,
+            None => {
+                // Otherwise the task is already done
+                if RUNNING.lock().contains(&id) {
+                    deferred.push(id);
+                }
+                continue;
+            }
+        };
+
+        entry.state.queued.store(false, Ordering::Relaxed);
+
+        RUNNING.lock().push(id);
+        let mut context = Context::from_waker(&entry.waker);
+        let poll = entry.task.poll(&mut context);
+        RUNNING.lock().pop();
+
+        if poll.is_pending() {
+            TASKS.lock().insert(id, entry);
+        }
+    }
+
+    for id in deferred {
+        TASK_QUEUE.push(id).expect("Task queue full");
+    }
+}
+
+fn sleep_if_idle() {
+    interrupts::disable();
+    if TASK_QUEUE.is_empty() {
+        interrupts::enable_and_hlt();
+    } else {
+        interrupts::enable();
+    }
+}
+
+impl TaskWaker {
+    fn wake_task(&self) {
+        // Prevents the same task from filling up the queue when woken repeatedly
+        if !self.queued.swap(true, Ordering::Relaxed) {
+            TASK_QUEUE.push(self.id).expect("Task queue full");
+        }
+    }
+}
+
+impl Wake for TaskWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_task();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.wake_task();
+    }
+}
@@ -0,0 +1,85 @@
+use core::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use crossbeam_queue::ArrayQueue;
+use futures_util::{
+    stream::{Stream, StreamExt},
+    task::AtomicWaker,
+};
+use spin::Once;
+
+use crate::{
+    display::graphics::flush_buffer_with,
+    game::{cursor_position, is_engine_searching, move_cursor, MOUSE},
+    ps2::MouseState,
+};
+
+const QUEUE_SIZE: usize = 128;
+
+static STATES: Once<ArrayQueue<MouseState>> = Once::new();
+static WAKER: AtomicWaker = AtomicWaker::new();
+
+pub struct MouseStream {
+    _private: (),
+}
+
+// Called by the mouse interrupt, must not allocate
+pub(crate) fn add_mouse_state(state: MouseState) {
+    if let Some(states) = STATES.get() {
+        // Dropping the state if the queue is full, logging here could deadlock
+        let _ = states.push(state);
+        WAKER.wake();
+    }
+}
+
+impl MouseStream {
+    pub fn new() -> Self {
+        STATES.call_once(|| ArrayQueue::new(QUEUE_SIZE));
+        Self { _private: () }
+    }
+}
+
+impl Stream for MouseStream {
+    type Item = MouseState;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<MouseState>> {
+        let states = STATES.get().expect("Mouse states not initialized");
+
+        if let Some(state) = states.pop() {
+            return Poll::Ready(Some(state));
+        }
+
+        WAKER.register(cx.waker());
+        match states.pop() {
+            Some(state) => {
+                WAKER.take();
+                Poll::Ready(Some(state))
+            }
+            None => Poll::Pending,
+        }
+    }
+}
+
+// The game only moves the cursor between events, this keeps it moving while the engine
+// is searching. It starts from where the game left the cursor when a search begins.
+pub async fn cursor_task() {
+    let mut states = MouseStream::new();
+    let (mut x, mut y) = cursor_position();
+    let mut searching = false;
+
+    while let Some(state) = states.next().await {
+        if !is_engine_searching() {
+            searching = false;
+            continue;
+        }
+        if !searching {
+            (x, y) = cursor_position();
+            searching = true;
+        }
+
+        (x, y) = move_cursor(x, y, &state);
+        flush_buffer_with(&MOUSE, x as usize, y as usize);
+    }
+}
@@ -1,11 +1,37 @@
 mod idt_tests;
 mod sample_test;
 
-use core::panic::PanicInfo;
+use core::{
+    panic::PanicInfo,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
 
 use vga::colors::Color16;
+use x86_64::instructions::{hlt, interrupts, port::Port};
 
-use crate::{print, println, set_text_color};
+use crate::{
+    interrupts::{end_of_interrupt, InterruptIndex},
+    set_text_color, time,
+};
+
+// Everything is written to the screen and the serial port, so the results can be read headless
+macro_rules! test_print {
+    ($($arg:tt)*) => {{
+        $crate::print!($($arg)*);
+        $crate::serial_print!($($arg)*);
+    }};
+}
+
+macro_rules! test_println {
+    () => (test_print!("\n"));
+    ($($arg:tt)*) => (test_print!("{}\n", format_args!($($arg)*)));
+}
+
+const TEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+// https://os.phil-opp.com/testing/#exiting-qemu
+const ISA_DEBUG_EXIT_PORT: u16 = 0xf4;
 
 static mut CURRENT_TEST_INDEX: usize = 0;
 static mut TESTS: Option<&[&dyn Testable]> = None;
@@ -13,18 +39,51 @@ static mut SHOULD_FAIL: bool = false;
 
 static mut SUCCESS_COUNT: usize = 0;
 
+// Tick at which the current test times out, 0 when no test is running
+static TEST_DEADLINE: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum QemuExitCode {
+    Success = 0x10,
+    Failed = 0x11,
+}
+
 fn print_ok() {
     set_text_color!(Color16::Green, Color16::Black);
-    println!("[OK]");
+    test_println!("[OK]");
     set_text_color!(Color16::White, Color16::Black);
 }
 
 fn print_fail() {
     set_text_color!(Color16::Red, Color16::Black);
-    println!("[FAIL]");
+    test_println!("[FAIL]");
     set_text_color!(Color16::White, Color16::Black);
 }
 
+pub fn exit_qemu(exit_code: QemuExitCode) -> ! {
+    let mut port: Port<u32> = Port::new(ISA_DEBUG_EXIT_PORT);
+    unsafe { port.write(exit_code as u32) };
+
+    // Only reached if the isa-debug-exit device is missing
+    loop {
+        hlt();
+    }
+}
+
+// Called by the timer interrupt, fails the current test the same way a panic would
+pub fn check_timeout() {
+    let deadline = TEST_DEADLINE.load(Ordering::Relaxed);
+    if deadline == 0 || time::ticks() < deadline {
+        return;
+    }
+
+    TEST_DEADLINE.store(0, Ordering::Relaxed);
+    end_of_interrupt(InterruptIndex::Timer);
+    interrupts::enable();
+    panic!("Test timed out after {:?}", TEST_TIMEOUT);
+}
+
 pub trait Testable {
     fn run(&self) -> ();
 }
@@ -34,7 +93,7 @@ where
     T: Fn(),
 {
     fn run(&self) {
-        print!("{}... ", core::any::type_name::<T>());
+        test_print!("{}... ", core::any::type_name::<T>());
         self();
         print_ok();
     }
@@ -43,7 +102,10 @@ where
 fn run_tests(tests: &[&dyn Testable], start: usize) {
     for i in start..tests.len() {
         unsafe { CURRENT_TEST_INDEX = i }
+        let deadline = time::ticks() + time::duration_to_ticks(TEST_TIMEOUT);
+        TEST_DEADLINE.store(deadline, Ordering::Relaxed);
         tests[i].run();
+        TEST_DEADLINE.store(0, Ordering::Relaxed);
         unsafe {
             if SHOULD_FAIL {
                 SHOULD_FAIL = false; // Just to display fail
@@ -57,13 +119,15 @@ fn run_tests(tests: &[&dyn Testable], start: usize) {
 pub fn test_runner(tests: &'static [&dyn Testable]) {
     unsafe { TESTS = Some(tests) };
     set_text_color!(Color16::LightBlue, Color16::Black);
-    println!("Running {} test(s)", tests.len());
-    println!();
+    test_println!("Running {} test(s)", tests.len());
+    test_println!();
     set_text_color!(Color16::White, Color16::Black);
     run_tests(tests, 0);
+    finish_tests();
 }
 
 pub fn test_panic_handler(info: &PanicInfo) -> ! {
+    TEST_DEADLINE.store(0, Ordering::Relaxed);
     unsafe {
         if SHOULD_FAIL {
             print_ok();
@@ -71,18 +135,26 @@ pub fn test_panic_handler(info: &PanicInfo) -> ! {
             SHOULD_FAIL = false;
         } else {
             print_fail();
-            println!("{}", info);
+            test_println!("{}", info);
         }
     }
 
     run_tests(unsafe { TESTS.unwrap() }, unsafe { CURRENT_TEST_INDEX } + 1);
+    finish_tests();
+}
+
+fn finish_tests() -> ! {
+    let (passed, total) = unsafe { (SUCCESS_COUNT, TESTS.map(|t| t.len()).unwrap_or(0)) };
 
-    println!();
+    test_println!();
     set_text_color!(Color16::LightBlue, Color16::Black);
-    unsafe {
-        println!("Passed {}/{} tests", SUCCESS_COUNT, TESTS.unwrap().len());
+    test_println!("Passed {}/{} tests", passed, total);
+
+    if passed == total {
+        exit_qemu(QemuExitCode::Success)
+    } else {
+        exit_qemu(QemuExitCode::Failed)
     }
-    loop {}
 }
 
 #[macro_export]
@@ -0,0 +1,184 @@
+use alloc::vec::Vec;
+use core::{
+    future::Future,
+    pin::Pin,
+    sync::atomic::{AtomicU64, Ordering},
+    task::{Context, Poll, Waker},
+    time::Duration,
+};
+
+use spin::Mutex;
+use x86_64::instructions::{hlt, interrupts, port::Port};
+
+// https://wiki.osdev.org/Programmable_Interval_Timer
+const PIT_FREQUENCY: u64 = 1_193_182;
+const PIT_CHANNEL_0: u16 = 0x40;
+const PIT_COMMAND: u16 = 0x43;
+
+// Channel 0, lobyte/hibyte access, mode 2 (rate generator), binary
+const PIT_RATE_GENERATOR: u8 = 0b0011_0100;
+
+pub const TICKS_PER_SECOND: u64 = 1000;
+
+const NANOS_PER_SECOND: u128 = 1_000_000_000;
+
+static TICKS: AtomicU64 = AtomicU64::new(0);
+
+// Tasks waiting on a `Sleep`, woken by the timer interrupt
+static SLEEPERS: Mutex<Vec<Sleeper>> = Mutex::new(Vec::new());
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Deadline {
+    tick: u64,
+}
+
+struct Sleeper {
+    id: u64,
+    tick: u64,
+    waker: Waker,
+}
+
+pub struct Sleep {
+    id: u64,
+    deadline: Deadline,
+}
+
+pub fn init() {
+    let divisor = (PIT_FREQUENCY / TICKS_PER_SECOND) as u16;
+
+    let mut command: Port<u8> = Port::new(PIT_COMMAND);
+    let mut data: Port<u8> = Port::new(PIT_CHANNEL_0);
+
+    unsafe {
+        command.write(PIT_RATE_GENERATOR);
+        data.write(divisor as u8);
+        data.write((divisor >> 8) as u8);
+    }
+}
+
+// Should only be called by the timer interrupt
+pub fn tick() {
+    let now = TICKS.fetch_add(1, Ordering::Relaxed) + 1;
+
+    // If the list is being modified, the sleepers will be woken on the next tick
+    if let Some(sleepers) = SLEEPERS.try_lock() {
+        for sleeper in sleepers.iter().filter(|s| s.tick <= now) {
+            sleeper.waker.wake_by_ref();
+        }
+    }
+}
+
+pub fn ticks() -> u64 {
+    TICKS.load(Ordering::Relaxed)
+}
+
+pub fn uptime() -> Duration {
+    ticks_to_duration(ticks())
+}
+
+// Halts until the duration has passed, interrupts need to be enabled
+pub fn sleep(duration: Duration) {
+    let deadline = Deadline::after(duration);
+    while !deadline.has_passed() {
+        hlt();
+    }
+}
+
+// Same as `sleep`, but lets the other tasks run in the meantime
+pub fn sleep_async(duration: Duration) -> Sleep {
+    static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+    Sleep {
+        id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+        deadline: Deadline::after(duration),
+    }
+}
+
+pub fn ticks_to_duration(ticks: u64) -> Duration {
+    let nanos = ticks as u128 * NANOS_PER_SECOND / TICKS_PER_SECOND as u128;
+    Duration::from_nanos(nanos as u64)
+}
+
+// Rounds up so that waiting for the ticks always lasts at least the duration
+pub fn duration_to_ticks(duration: Duration) -> u64 {
+    let ticks = (duration.as_nanos() * TICKS_PER_SECOND as u128 + NANOS_PER_SECOND - 1)
+        / NANOS_PER_SECOND;
+    ticks as u64
+}
+
+impl Deadline {
+    pub fn after(duration: Duration) -> Self {
+        Self {
+            tick: ticks() + duration_to_ticks(duration),
+        }
+    }
+
+    pub fn has_passed(&self) -> bool {
+        ticks() >= self.tick
+    }
+
+    pub fn remaining(&self) -> Duration {
+        ticks_to_duration(self.tick.saturating_sub(ticks()))
+    }
+}
+
+impl Sleep {
+    fn register(&self, waker: &Waker) {
+        interrupts::without_interrupts(|| {
+            let mut sleepers = SLEEPERS.lock();
+            match sleepers.iter_mut().find(|s| s.id == self.id) {
+                Some(sleeper) if sleeper.waker.will_wake(waker) => {}
+                Some(sleeper) => sleeper.waker = waker.clone(),
+                None => sleepers.push(Sleeper {
+                    id: self.id,
+                    tick: self.deadline.tick,
+                    waker: waker.clone(),
+                }),
+            }
+        });
+    }
+
+    fn unregister(&self) {
+        interrupts::without_interrupts(|| {
+            SLEEPERS.lock().retain(|s| s.id != self.id);
+        });
+    }
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        if !self.deadline.has_passed() {
+            self.register(cx.waker());
+
+            // The deadline could have passed while registering
+            if !self.deadline.has_passed() {
+                return Poll::Pending;
+            }
+        }
+
+        self.unregister();
+        Poll::Ready(())
+    }
+}
+
+impl Drop for Sleep {
+    fn drop(&mut self) {
+        self.unregister();
+    }
+}
+
+#[test_case]
+fn test_tick_conversion() {
+    assert_eq!(duration_to_ticks(Duration::from_secs(2)), 2 * TICKS_PER_SECOND);
+    assert_eq!(ticks_to_duration(TICKS_PER_SECOND), Duration::from_secs(1));
+    assert_eq!(duration_to_ticks(Duration::from_nanos(1)), 1);
+    assert_eq!(duration_to_ticks(Duration::ZERO), 0);
+}
+
+#[test_case]
+fn test_sleep() {
+    let start = uptime();
+    sleep(Duration::from_millis(20));
+    assert!(uptime() - start >= Duration::from_millis(20));
+}
@@ -1,14 +1,5 @@
 #!/usr/bin/sh
 set -e
 
-trap killgroup SIGINT
-
-killgroup(){
-  echo killing...
-  kill 0
-}
-
-cargo test $1 & # In case of release mode added
-sleep 1
-vncviewer :5900 &
-wait
\ No newline at end of file
+# Results are printed to serial and QEMU exits with the test status, no display needed
+cargo test $1 # In case of release mode added